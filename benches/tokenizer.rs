@@ -0,0 +1,67 @@
+// Measures how tokenizing time grows with the size of the source. The time
+// spent per byte should stay roughly constant as the source gets bigger.
+//
+// Run with `cargo bench --bench tokenizer`.
+
+use jack::tokenizer::Tokenizer;
+
+use std::time::{Duration, Instant};
+
+const CLASS: &str = r#"
+/** A class with a bit of everything the tokenizer has to handle */
+class Sample {
+	field int x, y; // coordinates
+	static Array values;
+
+	method int sum(int count) {
+		var int i, total;
+		let i = 0;
+		while (i < count) {
+			let total = total + (values[i] * 32767) - ~x;
+			let i = i + 1;
+		}
+		do Output.printString("total: ");
+		return total;
+	}
+}
+"#;
+
+const RUNS: usize = 5;
+
+// Returns the fastest time it took to tokenize the whole code
+fn time_tokenizing(code: &str) -> Duration {
+	(0..RUNS)
+		.map(|_| {
+			let start = Instant::now();
+			Tokenizer::new(code, "Sample.jack").for_each(drop);
+			start.elapsed()
+		})
+		.min()
+		.unwrap()
+}
+
+fn main() {
+	let mut per_byte = Vec::new();
+
+	println!("{:>12} {:>12} {:>10}", "bytes", "time (ms)", "ns/byte");
+
+	for power in 4..12 {
+		let code = CLASS.repeat(1 << power);
+		let time = time_tokenizing(&code);
+		let ns_per_byte = time.as_nanos() as f64 / code.len() as f64;
+
+		println!(
+			"{:>12} {:>12.3} {:>10.2}",
+			code.len(),
+			time.as_secs_f64() * 1000.0,
+			ns_per_byte
+		);
+		per_byte.push(ns_per_byte);
+	}
+
+	// With linear scaling, the largest input costs about as much per byte as the smallest
+	println!(
+		"\nns/byte growth from smallest to largest input: {:.2}x",
+		per_byte.last().unwrap() / per_byte.first().unwrap()
+	);
+}
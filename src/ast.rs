@@ -0,0 +1,282 @@
+use crate::span::Span;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use strum_macros::Display;
+
+/// A name in the code and where it is written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identifier {
+	pub name: String,
+	pub span: Span,
+}
+
+/// The type of a variable, a parameter or a return value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Type {
+	Int,
+	Char,
+	Boolean,
+	Class(String),
+}
+
+impl fmt::Display for Type {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Type::Int => write!(f, "int"),
+			Type::Char => write!(f, "char"),
+			Type::Boolean => write!(f, "boolean"),
+			Type::Class(name) => write!(f, "{}", name),
+		}
+	}
+}
+
+// Types are written in JSON as in the code, as in `int` or `Point`
+impl From<String> for Type {
+	fn from(name: String) -> Type {
+		match name.as_str() {
+			"int" => Type::Int,
+			"char" => Type::Char,
+			"boolean" => Type::Boolean,
+			_ => Type::Class(name),
+		}
+	}
+}
+
+impl From<Type> for String {
+	fn from(typing: Type) -> String {
+		typing.to_string()
+	}
+}
+
+/// A parsed `.jack` file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Class {
+	pub name: Identifier,
+	pub vars: Vec<ClassVarDec>,
+	pub subroutines: Vec<SubroutineDec>,
+	// The `/** ... */` comment before the class, if any
+	pub doc: Option<String>,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClassVarKind {
+	Static,
+	Field,
+}
+
+/// `static` or `field` followed by a type and one or more names
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassVarDec {
+	pub kind: ClassVarKind,
+	pub typing: Type,
+	pub type_span: Span,
+	pub names: Vec<Identifier>,
+	pub doc: Option<String>,
+	pub span: Span,
+}
+
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+pub enum SubroutineKind {
+	Constructor,
+	Function,
+	Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubroutineDec {
+	pub kind: SubroutineKind,
+	// `None` for `void`
+	pub return_type: Option<Type>,
+	// Where the return type, or `void`, is written
+	pub return_type_span: Span,
+	pub name: Identifier,
+	pub parameters: Vec<Parameter>,
+	pub body: SubroutineBody,
+	pub doc: Option<String>,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parameter {
+	pub typing: Type,
+	pub type_span: Span,
+	pub name: Identifier,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubroutineBody {
+	pub vars: Vec<VarDec>,
+	pub statements: Vec<Statement>,
+	pub span: Span,
+}
+
+/// `var` followed by a type and one or more names
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VarDec {
+	pub typing: Type,
+	pub type_span: Span,
+	pub names: Vec<Identifier>,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Statement {
+	Let(LetStatement),
+	If(IfStatement),
+	While(WhileStatement),
+	Do(DoStatement),
+	Return(ReturnStatement),
+}
+
+impl Statement {
+	pub fn span(&self) -> &Span {
+		match self {
+			Statement::Let(statement) => &statement.span,
+			Statement::If(statement) => &statement.span,
+			Statement::While(statement) => &statement.span,
+			Statement::Do(statement) => &statement.span,
+			Statement::Return(statement) => &statement.span,
+		}
+	}
+}
+
+/// `let name = value;` or `let name[index] = value;`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LetStatement {
+	pub name: Identifier,
+	pub index: Option<Expression>,
+	pub value: Expression,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IfStatement {
+	pub condition: Expression,
+	pub statements: Vec<Statement>,
+	// `None` without an `else` block
+	pub else_statements: Option<Vec<Statement>>,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WhileStatement {
+	pub condition: Expression,
+	pub statements: Vec<Statement>,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoStatement {
+	pub call: SubroutineCall,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReturnStatement {
+	pub value: Option<Expression>,
+	pub span: Span,
+}
+
+/// A term followed by operations applied from left to right, as Jack has no
+/// operator precedence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Expression {
+	pub term: Box<Term>,
+	pub operations: Vec<(BinaryOp, Term)>,
+	pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinaryOp {
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+	And,
+	Or,
+	LessThan,
+	GreaterThan,
+	Equal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnaryOp {
+	// -
+	Negate,
+	// ~
+	Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeywordConstant {
+	True,
+	False,
+	Null,
+	This,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum Term {
+	IntegerConstant {
+		value: u16,
+		span: Span,
+	},
+	StringConstant {
+		value: String,
+		span: Span,
+	},
+	KeywordConstant {
+		value: KeywordConstant,
+		span: Span,
+	},
+	Variable(Identifier),
+	// name[index]
+	ArrayAccess {
+		name: Identifier,
+		index: Box<Expression>,
+		span: Span,
+	},
+	SubroutineCall(SubroutineCall),
+	// (expression)
+	Parenthesized {
+		expression: Box<Expression>,
+		span: Span,
+	},
+	Unary {
+		op: UnaryOp,
+		term: Box<Term>,
+		span: Span,
+	},
+}
+
+impl Term {
+	pub fn span(&self) -> &Span {
+		match self {
+			Term::IntegerConstant { span, .. }
+			| Term::StringConstant { span, .. }
+			| Term::KeywordConstant { span, .. }
+			| Term::ArrayAccess { span, .. }
+			| Term::Parenthesized { span, .. }
+			| Term::Unary { span, .. } => span,
+			Term::Variable(name) => &name.span,
+			Term::SubroutineCall(call) => &call.span,
+		}
+	}
+}
+
+/// `name(arguments)` or `receiver.name(arguments)`, where the receiver is a
+/// variable or a class name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubroutineCall {
+	pub receiver: Option<Identifier>,
+	pub name: Identifier,
+	pub arguments: Vec<Expression>,
+	pub span: Span,
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Maps the characters of string constants to the Hack character set.
+///
+/// The Hack platform only knows the printable ASCII characters (32 to 126)
+/// and the special keys below, which can be written with their usual key
+/// symbols:
+///
+/// | code | key         | symbol |
+/// |------|-------------|--------|
+/// | 128  | newline     | `⏎`    |
+/// | 129  | backspace   | `⌫`    |
+/// | 130  | left arrow  | `←`    |
+/// | 131  | up arrow    | `↑`    |
+/// | 132  | right arrow | `→`    |
+/// | 133  | down arrow  | `↓`    |
+/// | 134  | home        | `↖`    |
+/// | 135  | end         | `↘`    |
+/// | 136  | page up     | `⇞`    |
+/// | 137  | page down   | `⇟`    |
+/// | 138  | insert      | `⎀`    |
+/// | 139  | delete      | `⌦`    |
+/// | 140  | esc         | `⎋`    |
+///
+/// The function keys F1 to F12 (141 to 152) have no Unicode symbol, so they
+/// cannot be written in string constants.
+///
+/// Other characters are rejected, unless a transliteration is given for them.
+#[derive(Default, Clone)]
+pub struct Charset {
+	transliterations: HashMap<char, String>,
+}
+
+/// A character of a string constant that has no Hack code
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedCharacter {
+	pub character: char,
+	// Byte offset of the character in the string constant
+	pub offset: usize,
+}
+
+impl fmt::Display for UnsupportedCharacter {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"character {:?} (U+{:04X}) is not in the Hack character set",
+			self.character, self.character as u32
+		)
+	}
+}
+
+const SPECIAL_KEYS: [(char, u16); 13] = [
+	('⏎', 128),
+	('⌫', 129),
+	('←', 130),
+	('↑', 131),
+	('→', 132),
+	('↓', 133),
+	('↖', 134),
+	('↘', 135),
+	('⇞', 136),
+	('⇟', 137),
+	('⎀', 138),
+	('⌦', 139),
+	('⎋', 140),
+];
+
+// Transliterations used by `Charset::with_default_transliterations`
+const DEFAULT_TRANSLITERATIONS: [(char, &str); 44] = [
+	('à', "a"),
+	('á', "a"),
+	('â', "a"),
+	('ä', "a"),
+	('ç', "c"),
+	('è', "e"),
+	('é', "e"),
+	('ê', "e"),
+	('ë', "e"),
+	('ì', "i"),
+	('í', "i"),
+	('î', "i"),
+	('ï', "i"),
+	('ñ', "n"),
+	('ò', "o"),
+	('ó', "o"),
+	('ô', "o"),
+	('ö', "o"),
+	('ù', "u"),
+	('ú', "u"),
+	('û', "u"),
+	('ü', "u"),
+	('À', "A"),
+	('Á', "A"),
+	('Â', "A"),
+	('Ä', "A"),
+	('Ç', "C"),
+	('È', "E"),
+	('É', "E"),
+	('Ê', "E"),
+	('Ñ', "N"),
+	('Ö', "O"),
+	('Ü', "U"),
+	('ß', "ss"),
+	('‘', "'"),
+	('’', "'"),
+	('“', "\""),
+	('”', "\""),
+	('–', "-"),
+	('—', "-"),
+	('…', "..."),
+	('€', "EUR"),
+	('£', "GBP"),
+	('\u{a0}', " "),
+];
+
+// The Hack code of a character, without transliteration
+fn hack_code(c: char) -> Option<u16> {
+	match c {
+		' '..='~' => Some(c as u16),
+		_ => SPECIAL_KEYS
+			.iter()
+			.find(|(key, _)| *key == c)
+			.map(|(_, code)| *code),
+	}
+}
+
+impl Charset {
+	/// A charset accepting only the characters of the Hack character set
+	pub fn new() -> Charset {
+		Charset::default()
+	}
+
+	/// A charset replacing common accented letters and typographic
+	/// punctuation with their closest ASCII equivalent
+	pub fn with_default_transliterations() -> Charset {
+		let mut charset = Charset::new();
+		for (from, to) in DEFAULT_TRANSLITERATIONS.iter() {
+			charset.transliterations.insert(*from, to.to_string());
+		}
+		charset
+	}
+
+	/// Writes `to` in place of `from`. `to` may only contain characters of
+	/// the Hack character set.
+	pub fn transliterate(&mut self, from: char, to: &str) -> Result<(), UnsupportedCharacter> {
+		if let Some((offset, character)) = to.char_indices().find(|(_, c)| hack_code(*c).is_none())
+		{
+			return Err(UnsupportedCharacter { character, offset });
+		}
+
+		self.transliterations.insert(from, to.to_string());
+		Ok(())
+	}
+
+	/// Adds the transliterations of a table with one `character=replacement`
+	/// entry per line. Empty lines and lines starting with `#` are ignored.
+	pub fn load_table(&mut self, table: &str) -> Result<(), String> {
+		for (number, line) in table.lines().enumerate() {
+			if line.trim().is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let mut chars = line.chars();
+			let entry = match (chars.next(), chars.next()) {
+				(Some(from), Some('=')) => self.transliterate(from, chars.as_str()),
+				_ => {
+					return Err(format!(
+						"line {}: expected `character=replacement`",
+						number + 1
+					))
+				}
+			};
+
+			if let Err(err) = entry {
+				return Err(format!("line {}: {}", number + 1, err));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns the Hack codes of the text, or its first character that
+	/// cannot be written with them
+	pub fn encode(&self, text: &str) -> Result<Vec<u16>, UnsupportedCharacter> {
+		let mut codes = Vec::with_capacity(text.len());
+
+		for (offset, character) in text.char_indices() {
+			if let Some(code) = hack_code(character) {
+				codes.push(code);
+				continue;
+			}
+
+			match self.transliterations.get(&character) {
+				Some(replacement) => codes.extend(replacement.chars().filter_map(hack_code)),
+				None => return Err(UnsupportedCharacter { character, offset }),
+			}
+		}
+
+		Ok(codes)
+	}
+}
@@ -0,0 +1,763 @@
+use crate::ast::*;
+use crate::charset::Charset;
+use crate::error::{CompileError, ErrorKind};
+use crate::signatures::SignatureTable;
+use crate::span::Span;
+use crate::symbol_table::{Symbol, SymbolKind, SymbolTable};
+use crate::visit::Visitor;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// The span of the character at byte `offset` of a string constant
+fn string_char_span(span: &Span, value: &str, offset: usize) -> Span {
+	let mut start = span.start;
+	start.advance("\"");
+	start.advance(&value[..offset]);
+
+	let length = value[offset..].chars().next().map_or(0, char::len_utf8);
+	let mut end = start;
+	end.advance(&value[offset..offset + length]);
+
+	Span::new(span.file.clone(), start, end)
+}
+
+// The count followed by the noun, in the plural unless it is 1
+fn count(count: usize, noun: &str) -> String {
+	if count == 1 {
+		format!("1 {}", noun)
+	} else {
+		format!("{} {}s", count, noun)
+	}
+}
+
+// The VM code pushing the value of a variable
+fn push_variable(symbol: &Symbol) -> String {
+	format!("push {} {}\n", symbol.kind.segment(), symbol.index)
+}
+
+fn op_code(op: BinaryOp) -> &'static str {
+	match op {
+		BinaryOp::Add => "add\n",
+		BinaryOp::Subtract => "sub\n",
+		BinaryOp::Multiply => "call Math.multiply 2\n",
+		BinaryOp::Divide => "call Math.divide 2\n",
+		BinaryOp::And => "and\n",
+		BinaryOp::Or => "or\n",
+		BinaryOp::LessThan => "lt\n",
+		BinaryOp::GreaterThan => "gt\n",
+		BinaryOp::Equal => "eq\n",
+	}
+}
+
+/// Translates the syntax tree of a class into VM code
+pub struct CodeGenerator {
+	class_name: String,
+	class_symbol_table: SymbolTable,
+	func_symbol_table: SymbolTable,
+	label_count: usize,
+	charset: Charset,
+	// The subroutines of the program, `None` if unknown
+	signatures: Option<Rc<SignatureTable>>,
+	// The subroutines of the class, to check calls within it when the
+	// program is unknown
+	class_signatures: Rc<SignatureTable>,
+	// The name of the subroutine being translated when it is a function,
+	// which has no current object
+	function: Option<Identifier>,
+	// The VM code generated so far
+	output: String,
+	errors: Vec<CompileError>,
+}
+
+impl CodeGenerator {
+	fn get_label(&mut self) -> String {
+		self.label_count += 1;
+		String::from(&format!("{}{}", self.class_name, self.label_count - 1))
+	}
+
+	fn find_symbol(&self, name: &str) -> Option<&Symbol> {
+		match self.func_symbol_table.get(name) {
+			Some(sym) => Some(sym),
+			None => self.class_symbol_table.get(name),
+		}
+	}
+
+	// Finds the variable, recording an error when it is not declared
+	fn lookup(&mut self, name: &Identifier) -> Option<Symbol> {
+		let symbol = self.find_symbol(&name.name).cloned();
+
+		if symbol.is_none() {
+			self.errors.push(CompileError::new(
+				ErrorKind::UndefinedVariable,
+				format!("undefined variable `{}`", name.name),
+				name.span.clone(),
+			));
+		}
+
+		symbol
+	}
+
+	pub fn new() -> CodeGenerator {
+		CodeGenerator {
+			class_name: String::new(),
+			class_symbol_table: SymbolTable::new(),
+			func_symbol_table: SymbolTable::new(),
+			label_count: 0,
+			charset: Charset::new(),
+			signatures: None,
+			class_signatures: Rc::new(SignatureTable::new()),
+			function: None,
+			output: String::new(),
+			errors: Vec::new(),
+		}
+	}
+
+	/// Sets how the characters of string constants are mapped to the Hack
+	/// character set. By default, other characters are rejected.
+	pub fn set_charset(&mut self, charset: Charset) {
+		self.charset = charset;
+	}
+
+	/// Sets the subroutines of every class of the program, so that calls
+	/// are checked against them. By default, only calls to the subroutines
+	/// of the translated class are checked.
+	pub fn set_signatures(&mut self, signatures: Rc<SignatureTable>) {
+		self.signatures = Some(signatures);
+	}
+
+	// Checks that the subroutine called on the class exists, is called the
+	// way it is declared, with or without an object, and is given as many
+	// arguments as it has parameters
+	fn check_call(&mut self, call: &SubroutineCall, class_name: &str, on_object: bool) {
+		let signatures = match &self.signatures {
+			Some(signatures) => signatures.clone(),
+			None if class_name == self.class_name => self.class_signatures.clone(),
+			None => return,
+		};
+
+		if !signatures.has_class(class_name) {
+			let receiver = call.receiver.as_ref().unwrap_or(&call.name);
+			let err = match self.find_symbol(&receiver.name) {
+				// A variable declared with a class that does not exist
+				Some(symbol) if on_object => CompileError::new(
+					ErrorKind::UnknownClass,
+					format!(
+						"cannot find class `{}`, the type of `{}`",
+						class_name, receiver.name
+					),
+					receiver.span.clone(),
+				)
+				.with_label(symbol.span.clone(), "declared here"),
+				_ => CompileError::new(
+					ErrorKind::UnknownClass,
+					format!("cannot find a variable or class named `{}`", class_name),
+					receiver.span.clone(),
+				),
+			};
+			self.errors.push(err);
+			return;
+		}
+
+		let signature = match signatures.get(class_name, &call.name.name) {
+			Some(signature) => signature,
+			None => {
+				if signatures.is_complete(class_name) {
+					self.errors.push(CompileError::new(
+						ErrorKind::UnknownSubroutine,
+						format!(
+							"class `{}` has no subroutine named `{}`",
+							class_name, call.name.name
+						),
+						call.name.span.clone(),
+					));
+				}
+				return;
+			}
+		};
+
+		// The error pointing at the declaration of the subroutine, if any
+		let error = |kind: ErrorKind, message: String| {
+			let err = CompileError::new(kind, message, call.span.clone());
+			match &signature.span {
+				Some(span) => err.with_label(span.clone(), "declared here"),
+				None => err,
+			}
+		};
+
+		if on_object && signature.kind != SubroutineKind::Method {
+			let err = error(
+				ErrorKind::WrongCallKind,
+				format!(
+					"`{}` is a {}, not a method",
+					signature.full_name(),
+					signature.kind
+				),
+			);
+			self.errors.push(err.with_help(&format!(
+				"call it through its class, as in `{}(...)`",
+				signature.full_name()
+			)));
+		} else if let (None, Some(function)) = (&call.receiver, self.function.clone()) {
+			// A method of the current object, in a function
+			let err = error(
+				ErrorKind::WrongCallKind,
+				format!(
+					"`{}` is a method, it cannot be called without an object in function `{}`",
+					signature.full_name(),
+					function.name
+				),
+			)
+			.with_label(function.span, "functions have no current object");
+			self.errors
+				.push(err.with_help(&format!("call it on a variable of type `{}`", class_name)));
+		} else if !on_object && signature.kind == SubroutineKind::Method {
+			let err = error(
+				ErrorKind::WrongCallKind,
+				format!(
+					"`{}` is a method, it cannot be called without an object",
+					signature.full_name()
+				),
+			);
+			self.errors
+				.push(err.with_help(&format!("call it on a variable of type `{}`", class_name)));
+		}
+
+		if signature.parameters.len() != call.arguments.len() {
+			let given = match call.arguments.len() {
+				1 => "1 was".to_string(),
+				given => format!("{} were", given),
+			};
+			self.errors.push(error(
+				ErrorKind::WrongArgumentCount,
+				format!(
+					"`{}` takes {} but {} given",
+					signature.full_name(),
+					count(signature.parameters.len(), "argument"),
+					given
+				),
+			));
+		}
+	}
+
+	/// Returns the VM code of the class, or every error found while
+	/// translating it, in source order
+	pub fn generate(&mut self, class: &Class) -> Result<String, Vec<CompileError>> {
+		self.visit_class(class);
+		let result = std::mem::take(&mut self.output);
+
+		if self.errors.is_empty() {
+			Ok(result)
+		} else {
+			let mut errors = std::mem::take(&mut self.errors);
+			errors.sort_by_key(|err| err.span.start);
+			Err(errors)
+		}
+	}
+}
+
+impl Visitor for CodeGenerator {
+	fn visit_class(&mut self, class: &Class) {
+		self.class_name = class.name.name.clone();
+
+		self.class_symbol_table = SymbolTable::for_class(class, &mut self.errors);
+
+		let mut class_signatures = SignatureTable::new();
+		class_signatures.add_class(class);
+		self.class_signatures = Rc::new(class_signatures);
+
+		let mut names = HashMap::new();
+		for subroutine in &class.subroutines {
+			let name = &subroutine.name;
+			if let Some(first) = names.insert(&name.name, name) {
+				let err = CompileError::new(
+					ErrorKind::DuplicateDeclaration,
+					format!("`{}` is declared twice", name.name),
+					name.span.clone(),
+				);
+				self.errors
+					.push(err.with_label(first.span.clone(), "first declared here"));
+			}
+		}
+
+		for subroutine in &class.subroutines {
+			self.visit_subroutine_dec(subroutine);
+		}
+	}
+
+	fn visit_subroutine_dec(&mut self, subroutine: &SubroutineDec) {
+		self.func_symbol_table =
+			SymbolTable::for_subroutine(&self.class_name, subroutine, &mut self.errors);
+		self.function = match subroutine.kind {
+			SubroutineKind::Function => Some(subroutine.name.clone()),
+			_ => None,
+		};
+
+		let local_count = self.func_symbol_table.count(SymbolKind::Local);
+		self.output.push_str(&format!(
+			"function {}.{} {}\n",
+			self.class_name, subroutine.name.name, local_count
+		));
+		if subroutine.kind == SubroutineKind::Constructor {
+			let field_count = self.class_symbol_table.count(SymbolKind::Field);
+			self.output
+				.push_str(&format!("push constant {}\n", field_count));
+			self.output.push_str("call Memory.alloc 1\n");
+			self.output.push_str("pop pointer 0\n");
+		}
+		if subroutine.kind == SubroutineKind::Method {
+			self.output.push_str("push argument 0\n");
+			self.output.push_str("pop pointer 0\n");
+		}
+
+		for statement in &subroutine.body.statements {
+			self.visit_statement(statement);
+		}
+	}
+
+	fn visit_let_statement(&mut self, statement: &LetStatement) {
+		// Symbol of the assigned variable
+		let symbol = self.lookup(&statement.name);
+		self.visit_expression(&statement.value);
+
+		let symbol = match symbol {
+			Some(sym) => sym,
+			None => return,
+		};
+
+		if let Some(index) = &statement.index {
+			self.output.push_str(&push_variable(&symbol));
+			self.visit_expression(index);
+			self.output.push_str("add\n");
+			self.output.push_str("pop pointer 1\n");
+			self.output.push_str("pop that 0\n");
+		} else {
+			self.output
+				.push_str(&format!("pop {} {}\n", symbol.kind.segment(), symbol.index));
+		}
+	}
+
+	fn visit_if_statement(&mut self, statement: &IfStatement) {
+		self.visit_expression(&statement.condition);
+		self.output.push_str("not\n");
+		let label_false = self.get_label();
+		let label_true = self.get_label();
+		self.output.push_str(&format!("if-goto {}\n", label_false));
+
+		for statement in &statement.statements {
+			self.visit_statement(statement);
+		}
+		self.output.push_str(&format!("goto {}\n", label_true));
+		self.output.push_str(&format!("label {}\n", label_false));
+		if let Some(else_statements) = &statement.else_statements {
+			for statement in else_statements {
+				self.visit_statement(statement);
+			}
+		}
+		self.output.push_str(&format!("label {}\n", label_true));
+	}
+
+	fn visit_while_statement(&mut self, statement: &WhileStatement) {
+		let loop_label = self.get_label();
+		let end_label = self.get_label();
+		self.output.push_str(&format!("label {}\n", loop_label));
+		self.visit_expression(&statement.condition);
+		self.output.push_str("not\n");
+		self.output.push_str(&format!("if-goto {}\n", end_label));
+		for statement in &statement.statements {
+			self.visit_statement(statement);
+		}
+		self.output.push_str(&format!("goto {}\n", loop_label));
+		self.output.push_str(&format!("label {}\n", end_label));
+	}
+
+	fn visit_do_statement(&mut self, statement: &DoStatement) {
+		self.visit_subroutine_call(&statement.call);
+		self.output.push_str("pop temp 0\n");
+	}
+
+	fn visit_return_statement(&mut self, statement: &ReturnStatement) {
+		match &statement.value {
+			Some(value) => self.visit_expression(value),
+			None => self.output.push_str("push constant 0\n"),
+		}
+		self.output.push_str("return\n");
+	}
+
+	fn visit_subroutine_call(&mut self, call: &SubroutineCall) {
+		let function_name;
+		let mut param_count = 0;
+
+		match &call.receiver {
+			Some(receiver) => match self.find_symbol(&receiver.name).cloned() {
+				// Method called on an object
+				Some(symbol) => {
+					match &symbol.typing {
+						Type::Class(class_name) => self.check_call(call, class_name, true),
+						typing => {
+							let err = CompileError::new(
+								ErrorKind::WrongCallKind,
+								format!(
+									"`{}` of type `{}` has no subroutines to call",
+									receiver.name, typing
+								),
+								receiver.span.clone(),
+							);
+							self.errors
+								.push(err.with_label(symbol.span.clone(), "declared here"));
+						}
+					}
+					self.output.push_str(&push_variable(&symbol));
+					function_name = format!("{}.{}", symbol.typing, call.name.name);
+					param_count += 1;
+				}
+				None => {
+					self.check_call(call, &receiver.name, false);
+					function_name = format!("{}.{}", receiver.name, call.name.name)
+				}
+			},
+			// Method called on the current object
+			None => {
+				let class_name = self.class_name.clone();
+				self.check_call(call, &class_name, true);
+				param_count += 1;
+				self.output.push_str("push pointer 0\n");
+				function_name = format!("{}.{}", self.class_name, call.name.name);
+			}
+		};
+
+		for argument in &call.arguments {
+			self.visit_expression(argument);
+		}
+		param_count += call.arguments.len();
+
+		self.output
+			.push_str(&format!("call {} {}\n", function_name, param_count));
+	}
+
+	fn visit_expression(&mut self, expression: &Expression) {
+		self.visit_term(&expression.term);
+
+		for (op, term) in &expression.operations {
+			self.visit_term(term);
+			self.output.push_str(op_code(*op));
+		}
+	}
+
+	fn visit_term(&mut self, term: &Term) {
+		match term {
+			Term::IntegerConstant { value, .. } => {
+				self.output.push_str(&format!("push constant {}\n", value))
+			}
+			Term::StringConstant { value, span } => {
+				let codes = match self.charset.encode(value) {
+					Ok(codes) => codes,
+					Err(err) => {
+						let span = string_char_span(span, value, err.offset);
+						let err = CompileError::new(
+							ErrorKind::UnsupportedCharacter,
+							err.to_string(),
+							span,
+						);
+						self.errors.push(err.with_note(
+							"the Hack character set holds the printable ASCII characters and the special keys",
+						));
+						return;
+					}
+				};
+
+				self.output
+					.push_str(&format!("push constant {}\n", codes.len()));
+				self.output.push_str("call String.new 1\n");
+
+				for code in codes {
+					self.output.push_str(&format!("push constant {}\n", code));
+					self.output.push_str("call String.appendChar 2\n");
+				}
+			}
+			Term::KeywordConstant { value, .. } => match value {
+				KeywordConstant::True => {
+					self.output.push_str("push constant 0\n");
+					self.output.push_str("not\n")
+				}
+				KeywordConstant::False => self.output.push_str("push constant 0\n"),
+				KeywordConstant::Null => self.output.push_str("push constant 0\n"),
+				KeywordConstant::This => self.output.push_str("push pointer 0\n"),
+			},
+			Term::Parenthesized { expression, .. } => self.visit_expression(expression),
+			Term::Unary { op, term, .. } => {
+				self.visit_term(term);
+				self.output.push_str(match op {
+					UnaryOp::Negate => "neg\n",
+					UnaryOp::Not => "not\n",
+				});
+			}
+			Term::ArrayAccess { name, index, .. } => {
+				let symbol = self.lookup(name);
+				self.visit_expression(index);
+
+				if let Some(symbol) = symbol {
+					self.output.push_str(&push_variable(&symbol));
+				}
+
+				self.output.push_str("add\n");
+				self.output.push_str("pop pointer 1\n");
+				self.output.push_str("push that 0\n");
+			}
+			Term::SubroutineCall(call) => self.visit_subroutine_call(call),
+			Term::Variable(name) => {
+				if let Some(symbol) = self.lookup(name) {
+					self.output.push_str(&push_variable(&symbol));
+				}
+			}
+		}
+	}
+}
+
+impl Default for CodeGenerator {
+	fn default() -> CodeGenerator {
+		CodeGenerator::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::parser::Parser;
+	use crate::tokenizer::Tokenizer;
+
+	// A class with a constructor, methods, arrays, conditions, loops and strings
+	const COUNTER: &str = r#"class Counter {
+	field int count;
+	field Array values;
+	static String name;
+
+	constructor Counter new(int size) {
+		let count = 0;
+		let values = Array.new(size);
+		return this;
+	}
+
+	method void add(int value) {
+		let values[count] = value;
+		let count = count + 1;
+		return;
+	}
+
+	method int sum() {
+		var int i, total;
+		let i = 0;
+		let total = 0;
+		while (i < count) {
+			let total = total + values[i];
+			let i = i + 1;
+		}
+		return total;
+	}
+
+	function void main() {
+		var Counter counter;
+		let counter = Counter.new(3);
+		do counter.add(-2 * 3 / 2);
+		if (~(counter.sum() = 0) & true) {
+			do Output.printString("Hi!");
+		} else {
+			let name = null;
+		}
+		return;
+	}
+}
+"#;
+
+	// The VM code of `COUNTER`, as the parser generated it before there was a
+	// syntax tree
+	const COUNTER_VM: &str = "function Counter.new 0
+push constant 2
+call Memory.alloc 1
+pop pointer 0
+push constant 0
+pop this 0
+push argument 0
+call Array.new 1
+pop this 1
+push pointer 0
+return
+function Counter.add 0
+push argument 0
+pop pointer 0
+push argument 1
+push this 1
+push this 0
+add
+pop pointer 1
+pop that 0
+push this 0
+push constant 1
+add
+pop this 0
+push constant 0
+return
+function Counter.sum 2
+push argument 0
+pop pointer 0
+push constant 0
+pop local 0
+push constant 0
+pop local 1
+label Counter0
+push local 0
+push this 0
+lt
+not
+if-goto Counter1
+push local 1
+push local 0
+push this 1
+add
+pop pointer 1
+push that 0
+add
+pop local 1
+push local 0
+push constant 1
+add
+pop local 0
+goto Counter0
+label Counter1
+push local 1
+return
+function Counter.main 1
+push constant 3
+call Counter.new 1
+pop local 0
+push local 0
+push constant 2
+neg
+push constant 3
+call Math.multiply 2
+push constant 2
+call Math.divide 2
+call Counter.add 2
+pop temp 0
+push local 0
+call Counter.sum 1
+push constant 0
+eq
+not
+push constant 0
+not
+and
+not
+if-goto Counter2
+push constant 3
+call String.new 1
+push constant 72
+call String.appendChar 2
+push constant 105
+call String.appendChar 2
+push constant 33
+call String.appendChar 2
+call Output.printString 1
+pop temp 0
+goto Counter3
+label Counter2
+push constant 0
+pop static 0
+label Counter3
+push constant 0
+return
+";
+
+	// Compiles the class as the only class of a program, returning the
+	// messages of the errors
+	fn compile(code: &str) -> Result<String, Vec<String>> {
+		let class = Parser::new(Tokenizer::new(code, "Main.jack"))
+			.parse()
+			.unwrap();
+		let mut signatures = SignatureTable::with_os();
+		signatures.add_class(&class);
+
+		let mut generator = CodeGenerator::new();
+		generator.set_signatures(Rc::new(signatures));
+		generator
+			.generate(&class)
+			.map_err(|errors| errors.into_iter().map(|err| err.message).collect())
+	}
+
+	#[test]
+	fn call_on_value_of_primitive_type() {
+		let errors =
+			compile("class Main { function void main() { var int x; do x.foo(); return; } }")
+				.unwrap_err();
+
+		assert_eq!(errors, vec!["`x` of type `int` has no subroutines to call"]);
+	}
+
+	#[test]
+	fn calls_on_unknown_classes() {
+		let errors = compile(
+			"class Main { function void main() { var Foo f; do contr.run(); do f.zap(1, 2, 3); return; } }",
+		)
+		.unwrap_err();
+
+		assert_eq!(
+			errors,
+			vec![
+				"cannot find a variable or class named `contr`",
+				"cannot find class `Foo`, the type of `f`",
+			]
+		);
+	}
+
+	#[test]
+	fn vm_code() {
+		assert_eq!(compile(COUNTER).unwrap(), COUNTER_VM);
+	}
+
+	#[test]
+	fn function_called_on_object() {
+		let errors = compile(
+			"class Main { function void f() { return; } method void g() { var Main m; do m.f(); return; } }",
+		)
+		.unwrap_err();
+
+		assert_eq!(errors, vec!["`Main.f` is a function, not a method"]);
+	}
+
+	#[test]
+	fn method_called_without_object_in_function() {
+		let errors = compile(
+			"class Main { method void f() { return; } function void main() { do f(); return; } }",
+		)
+		.unwrap_err();
+
+		assert_eq!(
+			errors,
+			vec!["`Main.f` is a method, it cannot be called without an object in function `main`"]
+		);
+	}
+
+	#[test]
+	fn method_called_through_class() {
+		let errors = compile(
+			"class Main { method void f() { return; } function void main() { do Main.f(); return; } }",
+		)
+		.unwrap_err();
+
+		assert_eq!(
+			errors,
+			vec!["`Main.f` is a method, it cannot be called without an object"]
+		);
+	}
+
+	#[test]
+	fn method_called_on_current_object() {
+		let code = "class Main {
+	constructor Main new() { do f(); return this; }
+	method void f() { return; }
+	method void g() { do f(); return; }
+}";
+
+		assert!(compile(code).is_ok());
+	}
+}
@@ -0,0 +1,320 @@
+use crate::error::CompileError;
+use crate::span::Span;
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use strum_macros::Display;
+
+const TAB_WIDTH: usize = 4;
+
+// ANSI styles of the rendered parts
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// How serious a diagnostic is
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+/// A secondary location shown with a diagnostic, as in "variable declared here"
+#[derive(Debug, Clone, Serialize)]
+pub struct Label {
+	#[serde(flatten)]
+	pub span: Span,
+	pub message: String,
+}
+
+/// Extra information shown with a diagnostic
+#[derive(Debug, Clone)]
+pub enum Annotation {
+	Label(Label),
+	Note(String),
+	Help(String),
+}
+
+/// A message about the code, rendered with the source it points at
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	pub code: Option<&'static str>,
+	pub message: String,
+	pub span: Span,
+	pub annotations: Vec<Annotation>,
+}
+
+impl Diagnostic {
+	pub fn new(severity: Severity, message: String, span: Span) -> Diagnostic {
+		Diagnostic {
+			severity,
+			code: None,
+			message,
+			span,
+			annotations: Vec::new(),
+		}
+	}
+
+	/// The secondary locations of the diagnostic
+	pub fn labels(&self) -> impl Iterator<Item = &Label> {
+		self.annotations
+			.iter()
+			.filter_map(|annotation| match annotation {
+				Annotation::Label(label) => Some(label),
+				_ => None,
+			})
+	}
+}
+
+impl From<CompileError> for Diagnostic {
+	fn from(err: CompileError) -> Diagnostic {
+		Diagnostic {
+			severity: Severity::Error,
+			code: Some(err.kind.code()),
+			message: err.message,
+			span: err.span,
+			annotations: err.annotations,
+		}
+	}
+}
+
+/// How diagnostics are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+	/// Rendered for people, on the standard error
+	Human,
+	/// One JSON object per line on the standard output, for tools
+	Json,
+}
+
+// The JSON object of a diagnostic. The location is flattened into `file`,
+// `start` and `end`, both positions holding a `line`, `column` and `offset`.
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+	severity: Severity,
+	code: Option<&'a str>,
+	message: &'a str,
+	#[serde(flatten)]
+	span: &'a Span,
+	labels: Vec<&'a Label>,
+	notes: Vec<&'a str>,
+	help: Vec<&'a str>,
+}
+
+impl Diagnostic {
+	/// The diagnostic as a single line JSON object:
+	///
+	/// ```text
+	/// {"severity":"error","code":"E0004","message":"undefined variable `cnt`",
+	///  "file":"Main.jack","start":{"line":12,"column":9,"offset":210},"end":{...},
+	///  "labels":[{"file":...,"start":...,"end":...,"message":...}],"notes":[],"help":[]}
+	/// ```
+	pub fn to_json(&self) -> String {
+		let mut notes = Vec::new();
+		let mut help = Vec::new();
+
+		for annotation in &self.annotations {
+			match annotation {
+				Annotation::Note(text) => notes.push(text.as_str()),
+				Annotation::Help(text) => help.push(text.as_str()),
+				Annotation::Label(_) => {}
+			}
+		}
+
+		let json = JsonDiagnostic {
+			severity: self.severity,
+			code: self.code,
+			message: &self.message,
+			span: &self.span,
+			labels: self.labels().collect(),
+			notes,
+			help,
+		};
+
+		serde_json::to_string(&json).expect("diagnostics are always serializable")
+	}
+}
+
+/// Whether diagnostics are worth colouring: both the output and the errors
+/// go to a terminal, and `NO_COLOR` is not set
+pub fn colour_supported() -> bool {
+	std::env::var_os("NO_COLOR").is_none()
+		&& std::io::stdout().is_terminal()
+		&& std::io::stderr().is_terminal()
+}
+
+// The line of the code holding the byte at `offset`, without its line break
+fn line_at(code: &str, offset: usize) -> &str {
+	let offset = offset.min(code.len());
+	let start = code[..offset].rfind('\n').map_or(0, |i| i + 1);
+	let end = code[offset..].find('\n').map_or(code.len(), |i| offset + i);
+
+	code[start..end].trim_end_matches('\r')
+}
+
+// The width of the text once tabs are expanded
+fn display_width(text: &str) -> usize {
+	text.chars()
+		.map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+		.sum()
+}
+
+/// Renders diagnostics like modern compilers do: a `file:line:col` header,
+/// then the source lines they point at, underlined, and their notes. Also
+/// writes them as JSON for tools.
+pub struct Emitter {
+	format: MessageFormat,
+	colour: bool,
+	// The code of every file, by file name
+	sources: HashMap<String, String>,
+}
+
+impl Emitter {
+	pub fn new(format: MessageFormat, colour: bool) -> Emitter {
+		Emitter {
+			format,
+			colour,
+			sources: HashMap::new(),
+		}
+	}
+
+	/// Registers the code of a file so diagnostics can quote it
+	pub fn add_source(&mut self, file: &str, code: &str) {
+		self.sources.insert(file.to_string(), code.to_string());
+	}
+
+	// The text with the given style, when colouring
+	fn paint(&self, style: &str, text: &str) -> String {
+		if self.colour {
+			format!("{}{}{}", style, text, RESET)
+		} else {
+			text.to_string()
+		}
+	}
+
+	// Quotes the first line of the span, underlined with `marker` and followed
+	// by the message
+	fn snippet(
+		&self,
+		span: &Span,
+		marker: char,
+		message: &str,
+		style: &str,
+		gutter: usize,
+	) -> String {
+		let code = match self.sources.get(&*span.file) {
+			Some(code) => code,
+			None => return String::new(),
+		};
+
+		let line = line_at(code, span.start.offset);
+		let before: String = line.chars().take(span.start.column - 1).collect();
+		let underlined: String = if span.end.line == span.start.line {
+			line.chars()
+				.skip(span.start.column - 1)
+				.take(span.end.column - span.start.column)
+				.collect()
+		} else {
+			line.chars().skip(span.start.column - 1).collect()
+		};
+
+		let padding = " ".repeat(display_width(&before));
+		let underline = marker.to_string().repeat(display_width(&underlined).max(1));
+		let bar = self.paint(BLUE, "|");
+
+		let mut result = format!(
+			"{} {} {}\n",
+			self.paint(
+				BLUE,
+				&format!("{:>width$}", span.start.line, width = gutter)
+			),
+			bar,
+			line.replace('\t', &" ".repeat(TAB_WIDTH))
+		);
+		result.push_str(&format!("{} {} {}", " ".repeat(gutter), bar, padding));
+		result.push_str(&self.paint(style, &underline));
+		if !message.is_empty() {
+			result.push(' ');
+			result.push_str(&self.paint(style, message));
+		}
+		result.push('\n');
+
+		result
+	}
+
+	/// The text of the diagnostic, ending with an empty line
+	pub fn render(&self, diagnostic: &Diagnostic) -> String {
+		let style = match diagnostic.severity {
+			Severity::Error => RED,
+			Severity::Warning => YELLOW,
+		};
+		let severity = match diagnostic.code {
+			Some(code) => format!("{}[{}]", diagnostic.severity, code),
+			None => diagnostic.severity.to_string(),
+		};
+
+		let mut result = format!(
+			"{}: {}: {}\n",
+			diagnostic.span,
+			self.paint(style, &severity),
+			self.paint(BOLD, &diagnostic.message)
+		);
+
+		let gutter = diagnostic
+			.labels()
+			.map(|label| label.span.start.line)
+			.chain(std::iter::once(diagnostic.span.start.line))
+			.max()
+			.map_or(1, |line| line.to_string().len());
+		let margin = " ".repeat(gutter);
+		let bar = self.paint(BLUE, "|");
+
+		if self.sources.contains_key(&*diagnostic.span.file) {
+			result.push_str(&format!("{} {}\n", margin, bar));
+		}
+		result.push_str(&self.snippet(&diagnostic.span, '^', "", style, gutter));
+
+		for annotation in &diagnostic.annotations {
+			let (kind, text) = match annotation {
+				Annotation::Label(label) => {
+					if label.span.file != diagnostic.span.file {
+						let arrow = self.paint(BLUE, ":::");
+						result.push_str(&format!("{} {} {}\n", margin, arrow, label.span));
+					} else {
+						result.push_str(&format!("{} {}\n", margin, bar));
+					}
+					result.push_str(&self.snippet(&label.span, '-', &label.message, BLUE, gutter));
+					continue;
+				}
+				Annotation::Note(text) => ("note", text),
+				Annotation::Help(text) => ("help", text),
+			};
+
+			result.push_str(&format!(
+				"{} = {}: {}\n",
+				margin,
+				self.paint(BOLD, kind),
+				text
+			));
+		}
+
+		// Separates the diagnostic from the next one
+		result.push('\n');
+		result
+	}
+
+	/// Writes the diagnostic in the format of the emitter. Nothing is written
+	/// once the output is closed, as when it is piped into `head`.
+	pub fn emit(&self, diagnostic: &Diagnostic) {
+		let _ = match self.format {
+			MessageFormat::Human => write!(io::stderr().lock(), "{}", self.render(diagnostic)),
+			MessageFormat::Json => writeln!(io::stdout().lock(), "{}", diagnostic.to_json()),
+		};
+	}
+}
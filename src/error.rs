@@ -0,0 +1,119 @@
+use crate::diagnostics::{Annotation, Label};
+use crate::span::Span;
+use crate::tokenizer::LexError;
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+	// The tokenizer could not read a token
+	Lexical,
+	// A token does not fit the Jack grammar
+	UnexpectedToken,
+	// The file ends in the middle of a class
+	UnexpectedEndOfFile,
+	UndefinedVariable,
+	// A string constant character has no Hack code
+	UnsupportedCharacter,
+	// A name declared twice in the same scope
+	DuplicateDeclaration,
+	// A class that is neither in the program nor in the OS, as the type of a
+	// declaration or the receiver of a call
+	UnknownClass,
+	// A call to a subroutine its class does not have
+	UnknownSubroutine,
+	// A call with more or fewer arguments than the subroutine has parameters
+	WrongArgumentCount,
+	// A method called without an object, or a function called on one
+	WrongCallKind,
+	// A value of a type that does not fit where it is used
+	TypeMismatch,
+	// More variables of a kind than their segment can index
+	TooManyVariables,
+}
+
+impl ErrorKind {
+	/// The code identifying the kind of error in diagnostics
+	pub fn code(self) -> &'static str {
+		match self {
+			ErrorKind::Lexical => "E0001",
+			ErrorKind::UnexpectedToken => "E0002",
+			ErrorKind::UnexpectedEndOfFile => "E0003",
+			ErrorKind::UndefinedVariable => "E0004",
+			ErrorKind::UnsupportedCharacter => "E0005",
+			ErrorKind::DuplicateDeclaration => "E0006",
+			ErrorKind::UnknownClass => "E0007",
+			ErrorKind::UnknownSubroutine => "E0008",
+			ErrorKind::WrongArgumentCount => "E0009",
+			ErrorKind::WrongCallKind => "E0010",
+			ErrorKind::TypeMismatch => "E0011",
+			ErrorKind::TooManyVariables => "E0012",
+		}
+	}
+}
+
+/// An error preventing a class from being compiled
+#[derive(Debug, Clone)]
+pub struct CompileError {
+	pub kind: ErrorKind,
+	pub message: String,
+	pub span: Span,
+	// Related locations, notes and help, in the order they are shown
+	pub annotations: Vec<Annotation>,
+}
+
+impl CompileError {
+	pub fn new(kind: ErrorKind, message: String, span: Span) -> CompileError {
+		CompileError {
+			kind,
+			message,
+			span,
+			annotations: Vec::new(),
+		}
+	}
+
+	/// The error for a token the tokenizer could not read
+	pub fn lexical(err: &LexError, span: Span) -> CompileError {
+		let error = CompileError::new(ErrorKind::Lexical, err.to_string(), span);
+
+		match err {
+			LexError::NewlineInString => {
+				error.with_note("string constants cannot span several lines")
+			}
+			LexError::IntegerOutOfRange(_) => {
+				error.with_note("Jack integers are 16-bit, negative values are written with `-`")
+			}
+			LexError::UnknownCharacter(c) if c.is_alphanumeric() => {
+				error.with_note("names may only contain ASCII letters, digits and `_`")
+			}
+			_ => error,
+		}
+	}
+
+	pub fn with_label(mut self, span: Span, message: &str) -> CompileError {
+		self.annotations.push(Annotation::Label(Label {
+			span,
+			message: message.to_string(),
+		}));
+		self
+	}
+
+	pub fn with_note(mut self, note: &str) -> CompileError {
+		self.annotations.push(Annotation::Note(note.to_string()));
+		self
+	}
+
+	pub fn with_help(mut self, help: &str) -> CompileError {
+		self.annotations.push(Annotation::Help(help.to_string()));
+		self
+	}
+}
+
+impl fmt::Display for CompileError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}: {}", self.span, self.message)
+	}
+}
+
+impl Error for CompileError {}
@@ -0,0 +1,129 @@
+use crate::ast::Class;
+
+use serde::{Deserialize, Serialize};
+
+/// The version of the AST JSON schema, raised whenever a node changes shape
+/// so that tools can reject trees they do not understand
+pub const AST_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct Document<'a> {
+	version: u32,
+	class: &'a Class,
+}
+
+// The version is checked beforehand
+#[derive(Deserialize)]
+struct OwnedDocument {
+	class: Class,
+}
+
+// Only the version, read first so that a newer tree is reported as such
+// rather than as a missing field
+#[derive(Deserialize)]
+struct Header {
+	version: u32,
+}
+
+/// Serialises the tree of a class as JSON, in the following schema:
+///
+/// ```text
+/// {"version": 2, "class": Class}
+/// ```
+///
+/// Nodes are objects named after the fields of the `ast` types. Every node
+/// has a `span`, `{"file", "start", "end"}`, both positions holding a `line`,
+/// `column` (in characters, from 1) and `offset` (in bytes, from 0), the end
+/// being exclusive. Identifiers are `{"name", "span"}` and types are written
+/// as in the code (`"int"`, `"Point"`), along with a `type_span` in
+/// declarations, or a `return_type_span` in subroutines. Optional fields are
+/// `null`: the `return_type` of a `void` subroutine, a missing `doc` comment,
+/// `index` and `else_statements`, and the `value` of a bare `return`.
+///
+/// Statements and terms have a `kind` naming their variant, along with the
+/// fields of that variant:
+///
+/// - statements: `Let`, `If`, `While`, `Do`, `Return`
+/// - terms: `IntegerConstant` and `StringConstant` with a `value`,
+///   `KeywordConstant` with a `value` of `True`, `False`, `Null` or `This`,
+///   `Variable` with the fields of an identifier, `ArrayAccess` with a `name`
+///   and an `index`, `SubroutineCall` with an optional `receiver`, a `name`
+///   and `arguments`, `Parenthesized` with an `expression`, and `Unary` with
+///   an `op` (`Negate` or `Not`) and a `term`
+///
+/// An expression holds its first `term` and its `operations`, `[op, term]`
+/// pairs applied from left to right, `op` being one of `Add`, `Subtract`,
+/// `Multiply`, `Divide`, `And`, `Or`, `LessThan`, `GreaterThan` and `Equal`.
+pub fn ast_json(class: &Class) -> String {
+	let document = Document {
+		version: AST_SCHEMA_VERSION,
+		class,
+	};
+
+	serde_json::to_string_pretty(&document).expect("trees are always serializable")
+}
+
+/// Reads back a tree written by `ast_json`
+pub fn ast_from_json(json: &str) -> Result<Class, String> {
+	let header: Header = serde_json::from_str(json).map_err(|err| err.to_string())?;
+
+	if header.version != AST_SCHEMA_VERSION {
+		return Err(format!(
+			"unsupported AST schema version {}, expected {}",
+			header.version, AST_SCHEMA_VERSION
+		));
+	}
+
+	let document: OwnedDocument = serde_json::from_str(json).map_err(|err| err.to_string())?;
+	Ok(document.class)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::parser::Parser;
+	use crate::tokenizer::Tokenizer;
+
+	fn parse(code: &str) -> Class {
+		Parser::new(Tokenizer::new(code, "Main.jack"))
+			.parse()
+			.unwrap()
+	}
+
+	#[test]
+	fn round_trip() {
+		let class = parse(
+			"/** A class */ class Main {
+	field Array a;
+	function int main(int n) {
+		var String s;
+		let a[n] = -(n + 1);
+		if (~(n = 0)) { do Output.printString(\"n\"); } else { let s = null; }
+		while (n < 10) { let n = n * 2; }
+		return n;
+	}
+	method void f() { return; }
+}",
+		);
+
+		assert_eq!(ast_from_json(&ast_json(&class)), Ok(class));
+	}
+
+	#[test]
+	fn other_versions_are_rejected() {
+		let json = ast_json(&parse("class Main {}"));
+		let header = format!("\"version\": {}", AST_SCHEMA_VERSION);
+
+		for version in &[AST_SCHEMA_VERSION - 1, AST_SCHEMA_VERSION + 1] {
+			let json = json.replacen(&header, &format!("\"version\": {}", version), 1);
+
+			assert_eq!(
+				ast_from_json(&json),
+				Err(format!(
+					"unsupported AST schema version {}, expected {}",
+					version, AST_SCHEMA_VERSION
+				))
+			);
+		}
+	}
+}
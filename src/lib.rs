@@ -0,0 +1,14 @@
+pub mod ast;
+pub mod charset;
+pub mod codegen;
+pub mod diagnostics;
+pub mod error;
+pub mod json;
+pub mod parser;
+pub mod signatures;
+pub mod span;
+pub mod symbol_table;
+pub mod tokenizer;
+pub mod typecheck;
+pub mod visit;
+pub mod xml;
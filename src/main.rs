@@ -1,59 +1,201 @@
-#[macro_use]
-extern crate lazy_static;
-
-use std::collections::VecDeque;
+use std::collections::HashMap;
 use std::env::args;
 use std::fs::{read_dir, read_to_string, File};
 use std::io::prelude::*;
 use std::io::BufWriter;
 use std::path::Path;
+use std::process::exit;
+use std::rc::Rc;
+
+use jack::ast::Class;
+use jack::charset::Charset;
+use jack::codegen::CodeGenerator;
+use jack::diagnostics::{colour_supported, Diagnostic, Emitter, MessageFormat, Severity};
+use jack::error::CompileError;
+use jack::json::ast_json;
+use jack::parser::{Parser, DEFAULT_ERROR_LIMIT};
+use jack::signatures::SignatureTable;
+use jack::tokenizer::{Token, TokenType, Tokenizer};
+use jack::typecheck::{Strictness, TypeChecker};
+use jack::xml::tokens_xml;
+
+const USAGE: &str =
+    "Usage: jack [--tokens | --xml | --ast] [--transliterate[=<table>]] [--error-limit=<count>]
+            [--message-format=<human | json>] [--type-check=<off | warn | error>] <path>";
+
+// What the program produces for every .jack file
+enum Mode {
+    // Xxx.vm, the compiled VM code
+    Compile,
+    // XxxT.xml, the tokens as output by the nand2tetris analyzer
+    Tokens,
+    // Xxx.xml, the parse tree as output by the nand2tetris analyzer
+    ParseTree,
+    // Xxx.json, the syntax tree in the versioned JSON schema
+    Ast,
+}
 
-mod tokenizer;
-use tokenizer::{Token, TokenType, Tokenizer};
+// Stops the program on an error that is not about the compiled code
+fn fatal(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    exit(1);
+}
 
-mod parser;
-use parser::Parser;
+struct Options {
+    mode: Mode,
+    path: String,
+    charset: Charset,
+    error_limit: usize,
+    message_format: MessageFormat,
+    strictness: Strictness,
+}
 
-fn tokens_for_file(path: &Path) -> Vec<Token> {
-    let content = match read_to_string(path) {
-        Ok(content) => content,
-        Err(err) => panic!("{}", err),
+// The default transliterations, plus the ones of the table file
+fn load_transliterations(table_path: &str) -> Charset {
+    let mut charset = Charset::with_default_transliterations();
+    let table = match read_to_string(table_path) {
+        Ok(table) => table,
+        Err(err) => fatal(&format!("{}: {}", table_path, err)),
     };
 
-    let mut tokenizer = Tokenizer::new(content);
+    if let Err(err) = charset.load_table(&table) {
+        fatal(&format!("{}: {}", table_path, err));
+    }
 
-    let mut result = Vec::new();
+    charset
+}
+
+fn parse_args() -> Options {
+    let mut mode = Mode::Compile;
+    let mut path = None;
+    let mut charset = Charset::new();
+    let mut error_limit = DEFAULT_ERROR_LIMIT;
+    let mut message_format = MessageFormat::Human;
+    let mut strictness = Strictness::Off;
 
-    loop {
-        let token = tokenizer.next();
-        if token.token == TokenType::EndOfFile {
-            return result;
+    for arg in args().skip(1) {
+        match arg.as_str() {
+            "--tokens" => mode = Mode::Tokens,
+            "--xml" => mode = Mode::ParseTree,
+            "--ast" => mode = Mode::Ast,
+            "--transliterate" => charset = Charset::with_default_transliterations(),
+            _ if arg.starts_with("--transliterate=") => {
+                charset = load_transliterations(&arg["--transliterate=".len()..])
+            }
+            _ if arg.starts_with("--error-limit=") => {
+                error_limit = match arg["--error-limit=".len()..].parse() {
+                    Ok(limit) => limit,
+                    Err(_) => fatal(USAGE),
+                }
+            }
+            "--message-format=human" => message_format = MessageFormat::Human,
+            "--message-format=json" => message_format = MessageFormat::Json,
+            "--type-check=off" => strictness = Strictness::Off,
+            "--type-check=warn" => strictness = Strictness::Warn,
+            "--type-check=error" => strictness = Strictness::Error,
+            _ if arg.starts_with("--") || path.is_some() => fatal(USAGE),
+            _ => path = Some(arg),
         }
-        result.push(token);
+    }
+
+    match path {
+        Some(path) => Options {
+            mode,
+            path,
+            charset,
+            error_limit,
+            message_format,
+            strictness,
+        },
+        None => fatal(USAGE),
     }
 }
 
-fn vm_from_tokens(tokens: Vec<Token>) -> String {
-    let mut parser = Parser::new(VecDeque::from(tokens));
+fn parse_file(content: &str, path: &Path, options: &Options) -> Result<Class, Vec<CompileError>> {
+    let tokenizer = Tokenizer::new(content, &path.to_string_lossy());
+    let mut parser = Parser::new(tokenizer);
+    parser.set_error_limit(options.error_limit);
 
     parser.parse()
 }
 
-fn main() {
-    let args: Vec<String> = args().collect();
+fn vm_for_class(
+    class: &Class,
+    options: &Options,
+    signatures: &Rc<SignatureTable>,
+) -> Result<String, Vec<CompileError>> {
+    let mut generator = CodeGenerator::new();
+    generator.set_charset(options.charset.clone());
+    generator.set_signatures(signatures.clone());
+    generator.generate(class)
+}
+
+fn parse_tree_xml_for_file(
+    content: &str,
+    path: &Path,
+    options: &Options,
+) -> Result<String, Vec<CompileError>> {
+    let tokenizer = Tokenizer::new(content, &path.to_string_lossy());
+    let mut parser = Parser::new(tokenizer);
+    parser.set_error_limit(options.error_limit);
+
+    parser.parse_xml()
+}
+
+fn ast_json_for_file(
+    content: &str,
+    path: &Path,
+    options: &Options,
+) -> Result<String, Vec<CompileError>> {
+    let class = parse_file(content, path, options)?;
+
+    Ok(ast_json(&class))
+}
+
+fn tokens_xml_for_file(content: &str, path: &Path) -> Result<String, Vec<CompileError>> {
+    let tokens: Vec<Token> = Tokenizer::new(content, &path.to_string_lossy()).collect();
+
+    let errors: Vec<CompileError> = tokens
+        .iter()
+        .filter_map(|token| match &token.token {
+            TokenType::Error(err) => Some(CompileError::lexical(err, token.span.clone())),
+            _ => None,
+        })
+        .collect();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(tokens_xml(tokens))
+}
 
-    if args.len() != 2 {
-        panic!("Usage: jack <path>");
+fn write_file(path: &Path, content: &str) {
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(err) => fatal(&format!("{}: {}", path.display(), err)),
     };
+    let mut writer = BufWriter::new(file);
+    match writer.write_all(content.as_bytes()) {
+        Ok(()) => {}
+        Err(err) => fatal(&format!("{}: {}", path.display(), err)),
+    };
+}
+
+fn main() {
+    let options = parse_args();
 
-    let dir_path = Path::new(&args[1]);
+    let dir_path = Path::new(&options.path);
 
     let read_dir = match read_dir(dir_path) {
         Ok(dir) => dir,
-        Err(_) => panic!("Invalid path"),
+        Err(err) => fatal(&format!("{}: {}", options.path, err)),
     };
 
-    read_dir
+    let mut emitter = Emitter::new(options.message_format, colour_supported());
+    let mut failed = false;
+
+    let files: Vec<String> = read_dir
         .filter_map(|file| match file {
             Ok(file) => {
                 let file_name = file.file_name().to_string_lossy().into_owned();
@@ -65,24 +207,93 @@ fn main() {
             }
             Err(_) => None,
         })
-        .for_each(|file| {
-            let path_string = &format!("{}/{}", args[1], file);
-            let path = Path::new(path_string);
-            let tokens = tokens_for_file(path);
-            let vm = vm_from_tokens(tokens);
-
-            let mut out_file_name = file.clone();
-            out_file_name.truncate(file.len() - 5);
-            let out_path_string = &format!("{}/{}.vm", args[1], out_file_name);
-            let out_path = Path::new(out_path_string);
-            let file = match File::create(out_path) {
-                Ok(file) => file,
-                Err(err) => panic!("{}", err),
-            };
-            let mut writer = BufWriter::new(file);
-            match writer.write(vm.as_bytes()) {
-                Ok(_) => {}
-                Err(err) => panic!("{}", err),
+        .collect();
+
+    // Every file is read before any output is produced, as compiling a class
+    // needs the subroutines of the others
+    let sources: Vec<(String, String)> = files
+        .into_iter()
+        .map(|file| {
+            let path_string = format!("{}/{}", options.path, file);
+            let content = match read_to_string(&path_string) {
+                Ok(content) => content,
+                Err(err) => fatal(&format!("{}: {}", path_string, err)),
             };
-        });
+            emitter.add_source(&path_string, &content);
+            (file, content)
+        })
+        .collect();
+
+    // The first pass of the compilation parses every class and collects the
+    // subroutines they declare
+    let mut classes = HashMap::new();
+    let mut signatures = SignatureTable::with_os();
+    if let Mode::Compile = options.mode {
+        for (file, content) in &sources {
+            let path_string = format!("{}/{}", options.path, file);
+            let class = parse_file(content, Path::new(&path_string), &options);
+
+            match &class {
+                Ok(class) => signatures.add_class(class),
+                // Calls to the class cannot be checked, but are not on an
+                // unknown class either
+                Err(_) => signatures.declare_class(&file[..file.len() - 5]),
+            }
+            classes.insert(file.clone(), class);
+        }
+    }
+    let signatures = Rc::new(signatures);
+
+    for (file, content) in &sources {
+        let path_string = &format!("{}/{}", options.path, file);
+        let path = Path::new(path_string);
+
+        let mut out_file_name = file.clone();
+        out_file_name.truncate(file.len() - 5);
+
+        // Type errors, which are only warnings unless the type checking is strict
+        let mut diagnostics = Vec::new();
+
+        let (output, extension) = match options.mode {
+            Mode::Compile => {
+                let class = classes.remove(file).expect("every file is parsed");
+                let output = class.and_then(|class| {
+                    let mut checker = TypeChecker::new(options.strictness);
+                    checker.set_signatures(signatures.clone());
+                    diagnostics = checker.check(&class);
+
+                    vm_for_class(&class, &options, &signatures)
+                });
+                (output, ".vm")
+            }
+            Mode::Tokens => (tokens_xml_for_file(content, path), "T.xml"),
+            Mode::ParseTree => (parse_tree_xml_for_file(content, path, &options), ".xml"),
+            Mode::Ast => (ast_json_for_file(content, path, &options), ".json"),
+        };
+
+        let type_errors = diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error);
+
+        match output {
+            Ok(output) if !type_errors => {
+                let out_path_string = &format!("{}/{}{}", options.path, out_file_name, extension);
+                write_file(Path::new(out_path_string), &output);
+            }
+            Ok(_) => failed = true,
+            Err(errors) => {
+                diagnostics.extend(errors.into_iter().map(Diagnostic::from));
+                failed = true;
+            }
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        for diagnostic in &diagnostics {
+            emitter.emit(diagnostic);
+        }
+    }
+
+    if failed {
+        exit(1);
+    }
 }
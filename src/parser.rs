@@ -1,708 +1,949 @@
-use crate::tokenizer::{Token, TokenType};
-
-use std::collections::{HashMap, VecDeque};
-
-#[derive(Clone)]
-struct Symbol {
-	pub kind: String,
-	pub typing: String,
-	pub index: u8,
+use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
+use crate::span::{Position, Span};
+use crate::tokenizer::{Keyword, Symbol, Token, TokenType};
+use crate::xml::XmlWriter;
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// How many errors are reported for a file before the parser gives up
+pub const DEFAULT_ERROR_LIMIT: usize = 20;
+
+/// Builds the syntax tree of a class from its tokens
+pub struct Parser<'a> {
+	tokens: Box<dyn Iterator<Item = Token<'a>> + 'a>,
+	// Tokens pulled from the stream but not consumed yet
+	lookahead: VecDeque<Token<'a>>,
+	// Records the parse tree when parsing for the analyzer output
+	xml: Option<XmlWriter>,
+	// Span of the last token pulled from the stream
+	last_span: Span,
+	// Span of the last token consumed
+	previous_span: Option<Span>,
+	// Errors the parser recovered from
+	errors: Vec<CompileError>,
+	error_limit: usize,
 }
 
-pub struct Parser {
-	tokens: VecDeque<Token>,
-	class_name: String,
-	class_symbol_table: HashMap<String, Symbol>,
-	func_symbol_table: HashMap<String, Symbol>,
-	label_count: u8,
+// Whether the token starts a class variable or subroutine declaration
+fn starts_class_member(token: &Token) -> bool {
+	match token.token {
+		TokenType::Keyword(keyword) => matches!(
+			keyword,
+			Keyword::Static | Keyword::Field | Keyword::Constructor | Keyword::Function | Keyword::Method
+		),
+		_ => false,
+	}
 }
 
-impl Parser {
-	fn get_func_local_count(&self) -> usize {
-		self
-			.func_symbol_table
-			.iter()
-			.filter(|(_, symbol)| symbol.kind == "local")
-			.count()
+// Whether the token starts a statement
+fn starts_statement(token: &Token) -> bool {
+	match token.token {
+		TokenType::Keyword(keyword) => matches!(
+			keyword,
+			Keyword::Let | Keyword::If | Keyword::While | Keyword::Do | Keyword::Return
+		),
+		_ => false,
 	}
+}
 
-	fn get_class_field_count(&self) -> usize {
-		self
-			.class_symbol_table
-			.iter()
-			.filter(|(_, symbol)| symbol.kind == "field")
-			.count()
+impl<'a> Parser<'a> {
+	// Pulls tokens from the stream until `n + 1` of them are buffered. A
+	// stream that stops without an `EndOfFile` token is treated as if it had one.
+	fn fill_lookahead(&mut self, n: usize) {
+		while self.lookahead.len() <= n {
+			let token = match self.tokens.next() {
+				Some(token) => token,
+				None => {
+					let end = self.last_span.end;
+					let span = Span::new(self.last_span.file.clone(), end, end);
+					Token::new(TokenType::EndOfFile, "", span)
+				}
+			};
+			self.last_span = token.span.clone();
+			self.lookahead.push_back(token);
+		}
 	}
 
-	fn get_label(&mut self) -> String {
-		self.label_count += 1;
-		String::from(&format!("{}{}", self.class_name, self.label_count - 1))
-	}
+	// Consumes the next token. The end of the file and lexical errors are
+	// never consumed, they are reported instead.
+	fn next(&mut self) -> Result<Token<'a>, CompileError> {
+		let token = self.peek()?;
 
-	fn find_symbol(&self, name: &String) -> Option<&Symbol> {
-		match self.func_symbol_table.get(name) {
-			Some(sym) => Some(sym),
-			None => self.class_symbol_table.get(name),
+		match &token.token {
+			TokenType::EndOfFile => {
+				return Err(CompileError::new(
+					ErrorKind::UnexpectedEndOfFile,
+					String::from("unexpected end of file"),
+					token.span,
+				))
+			}
+			TokenType::Error(err) => return Err(CompileError::lexical(err, token.span.clone())),
+			_ => {}
 		}
-	}
 
-	fn new_func_symbol_table(&mut self) {
-		self.func_symbol_table = HashMap::new();
+		self.lookahead.pop_front();
+		self.previous_span = Some(token.span.clone());
+		if let Some(xml) = &mut self.xml {
+			xml.token(&token);
+		}
+		Ok(token)
 	}
 
-	fn add_symbol_in_class(&mut self, name: &String, kind: &String, typing: &String) {
-		let mut index = 0;
-		let same_kind: Vec<(&String, &Symbol)> = self
-			.class_symbol_table
-			.iter()
-			.filter(|(_, sym)| sym.kind == *kind)
-			.collect();
-		if same_kind.len() != 0 {
-			let (_, max_sym) = same_kind
-				.iter()
-				.max_by(|(_, sym1), (_, sym2)| sym1.index.cmp(&sym2.index))
-				.unwrap();
-			index = max_sym.index + 1;
-		};
-		self.class_symbol_table.insert(
-			name.to_string(),
-			Symbol {
-				kind: kind.to_string(),
-				typing: typing.to_string(),
-				index: index,
-			},
-		);
+	fn peek(&mut self) -> Result<Token<'a>, CompileError> {
+		self.peek_nth(0)
 	}
 
-	fn add_symbol_in_func(&mut self, name: &String, kind: &String, typing: &String) {
-		let mut index = 0;
-		let same_kind: Vec<(&String, &Symbol)> = self
-			.func_symbol_table
-			.iter()
-			.filter(|(_, sym)| sym.kind == *kind)
-			.collect();
-		if same_kind.len() != 0 {
-			let (_, max_sym) = same_kind
-				.iter()
-				.max_by(|(_, sym1), (_, sym2)| sym1.index.cmp(&sym2.index))
-				.unwrap();
-			index = max_sym.index + 1;
-		};
-		self.func_symbol_table.insert(
-			name.to_string(),
-			Symbol {
-				kind: kind.to_string(),
-				typing: typing.to_string(),
-				index: index,
-			},
-		);
+	// Returns the token `n` positions ahead without consuming anything
+	fn peek_nth(&mut self, n: usize) -> Result<Token<'a>, CompileError> {
+		self.fill_lookahead(n);
+		Ok(self.lookahead[n].clone())
 	}
 
-	fn next(&mut self) -> Token {
-		self.tokens.pop_front().unwrap()
+	// The error for a token other than the expected ones. A lexical error
+	// token is reported as such.
+	fn unexpected(expected: &str, token: Token) -> CompileError {
+		if let TokenType::Error(err) = &token.token {
+			return CompileError::lexical(err, token.span.clone());
+		}
+
+		CompileError::new(
+			ErrorKind::UnexpectedToken,
+			format!("expected {}, found {}", expected, token),
+			token.span,
+		)
 	}
 
-	fn peek(&mut self) -> Token {
-		self.tokens.front().unwrap().clone()
+	// The error for a token that cannot start a term
+	fn expected_term(token: Token) -> CompileError {
+		Parser::unexpected("an expression", token)
 	}
 
-	fn parse_class(&mut self) -> String {
-		self.next(); // class
-		self.class_name = self.next().value;
-		self.next(); // {
+	// Consumes the next token, which must be the given symbol. The context
+	// tells where the symbol is expected, as in "after let statement".
+	fn expect(&mut self, symbol: Symbol, context: &str) -> Result<Token<'a>, CompileError> {
+		let token = self.peek()?;
 
-		// Optional class variables declaration
-		loop {
-			let next_token = self.peek();
+		if !token.is_symbol(symbol) {
+			let expected = format!("`{}` {}", symbol, context);
+			let mut err = Parser::unexpected(&expected, token.clone());
 
-			if next_token.value != "static" && next_token.value != "field" {
-				break;
-			};
+			// Points at the end of the previous line, where the symbol is usually missing
+			if let Some(previous) = &self.previous_span {
+				if err.kind == ErrorKind::UnexpectedToken && previous.end.line < token.span.start.line {
+					err = err.with_label(previous.clone(), &format!("expected `{}` after this", symbol));
+				}
+			}
 
-			self.parse_class_var_dec();
+			return Err(err);
 		}
 
-		let mut result = String::new();
-
-		// Optional subroutines declaration
-		loop {
-			let next_token = self.peek();
+		self.next()
+	}
 
-			if next_token.value != "constructor"
-				&& next_token.value != "function"
-				&& next_token.value != "method"
-			{
-				break;
-			};
+	// Consumes the next token, which must be an identifier naming `what`
+	fn expect_identifier(&mut self, what: &str) -> Result<Token<'a>, CompileError> {
+		let token = self.peek()?;
 
-			result.push_str(&self.parse_subroutine_dec());
+		if token.token != TokenType::Identifier {
+			return Err(Parser::unexpected(what, token));
 		}
 
-		self.next(); // }
-		result
+		self.next()
 	}
 
-	fn parse_subroutine_dec(&mut self) -> String {
-		self.new_func_symbol_table();
-		let mut result = String::new();
-		let kind = self.next().value; // function, method or constructor
+	// Skips the rest of a statement in error: up to and including a `;` or a
+	// block in braces, or up to a `}`, a statement or the next class member
+	fn skip_statement(&mut self) {
+		let mut depth = 0;
 
-		if kind == "method" {
-			self.add_symbol_in_func(
-				&"this".to_string(),
-				&"argument".to_string(),
-				&self.class_name.clone(),
-			);
-		}
+		loop {
+			self.fill_lookahead(0);
+			let token = &self.lookahead[0];
 
-		self.next(); // void or type
+			if token.token == TokenType::EndOfFile || starts_class_member(token) {
+				return;
+			}
+			if depth == 0 && (token.is_symbol(Symbol::RightBrace) || starts_statement(token)) {
+				return;
+			}
 
-		let subroutine_name = self.parse_subroutine_name();
-		self.next().value; // {
+			let end = match token.token {
+				TokenType::Symbol(Symbol::LeftBrace) => {
+					depth += 1;
+					false
+				}
+				TokenType::Symbol(Symbol::RightBrace) => {
+					depth -= 1;
+					depth == 0
+				}
+				TokenType::Symbol(Symbol::Semicolon) => depth == 0,
+				_ => false,
+			};
 
-		self.parse_parameter_list();
+			self.lookahead.pop_front();
+			if end {
+				return;
+			}
+		}
+	}
 
-		self.next(); // {
+	// Skips the rest of a class member in error, up to the next one
+	fn skip_class_member(&mut self) {
+		loop {
+			self.fill_lookahead(0);
+			let token = &self.lookahead[0];
 
-		let subroutine_body = self.parse_subroutine_body();
-		let local_count = self.get_func_local_count();
-		result.push_str(&format!(
-			"function {}.{} {}\n",
-			self.class_name, subroutine_name, local_count
-		));
-		if kind == "constructor" {
-			result.push_str(&format!("push constant {}\n", self.get_class_field_count()));
-			result.push_str("call Memory.alloc 1\n");
-			result.push_str("pop pointer 0\n");
+			if token.token == TokenType::EndOfFile || starts_class_member(token) {
+				return;
+			}
+
+			self.lookahead.pop_front();
 		}
-		if kind == "method" {
-			result.push_str("push argument 0\n");
-			result.push_str("pop pointer 0\n");
+	}
+
+	// Records the error and skips tokens to go on parsing after it. Once the
+	// error limit or the end of the file is reached, the error is returned to
+	// stop parsing instead.
+	fn recover(&mut self, err: CompileError, skip: fn(&mut Self)) -> Result<(), CompileError> {
+		if self.errors.len() + 1 >= self.error_limit {
+			return Err(err);
 		}
-		result.push_str(&subroutine_body);
 
-		result
-	}
+		skip(self);
+		if self.peek()?.token == TokenType::EndOfFile {
+			return Err(err);
+		}
 
-	fn parse_subroutine_name(&mut self) -> String {
-		self.next().value
+		self.errors.push(err);
+		Ok(())
 	}
 
-	fn parse_subroutine_body(&mut self) -> String {
-		let mut result = String::new();
-		self.next(); // {
+	// Turns the result of a parse into the errors found along the way, if any
+	fn finish<T>(&mut self, result: Result<T, CompileError>) -> Result<T, Vec<CompileError>> {
+		let mut errors = std::mem::take(&mut self.errors);
 
-		loop {
-			let var_or_else = self.peek();
-
-			if var_or_else.value != "var" {
-				break;
+		match result {
+			Ok(result) if errors.is_empty() => Ok(result),
+			Ok(_) => Err(errors),
+			Err(err) => {
+				errors.push(err);
+				Err(errors)
 			}
+		}
+	}
 
-			self.parse_var_dec();
+	// Starts a parse tree element, when recording one
+	fn open(&mut self, tag: &str) {
+		if let Some(xml) = &mut self.xml {
+			xml.open(tag);
 		}
+	}
 
-		result.push_str(&self.parse_statements());
+	// Ends a parse tree element, when recording one
+	fn close(&mut self, tag: &str) {
+		if let Some(xml) = &mut self.xml {
+			xml.close(tag);
+		}
+	}
 
-		self.next(); // }
-		result
+	// The span from the start of `start` to the end of the last consumed token
+	fn span_from(&self, start: &Span) -> Span {
+		let end = self.previous_span.as_ref().map_or(start.end, |span| span.end);
+		Span::new(start.file.clone(), start.start, end)
 	}
 
-	fn parse_var_dec(&mut self) {
-		self.next(); // var
-		let typing = self.parse_type();
-		let mut name = self.parse_var_name();
+	fn parse_class(&mut self) -> Result<Class, CompileError> {
+		self.open("class");
+		let class_token = self.peek()?;
+		if !class_token.is_keyword(Keyword::Class) {
+			return Err(Parser::unexpected("`class`", class_token));
+		}
+		self.next()?; // class
+		let name = self.parse_identifier("class name")?;
+		self.expect(Symbol::LeftBrace, "after class name")?;
 
+		// Optional class variables declaration
+		let mut vars = Vec::new();
 		loop {
-			self.add_symbol_in_func(&name, &"local".to_string(), &typing);
+			let next_token = self.peek()?;
 
-			let next_token = self.peek();
+			if !next_token.is_keyword(Keyword::Static) && !next_token.is_keyword(Keyword::Field) {
+				break;
+			};
 
-			if next_token.value == ";" {
-				self.next(); // ;
-				return;
+			match self.parse_class_var_dec() {
+				Ok(var_dec) => vars.push(var_dec),
+				Err(err) => self.recover(err, Parser::skip_class_member)?,
 			}
-
-			self.next(); // ,
-			name = self.parse_var_name();
 		}
-	}
-
-	fn parse_statements(&mut self) -> String {
-		let mut result = String::new();
 
+		// Optional subroutines declaration
+		let mut subroutines = Vec::new();
 		loop {
-			let next_elem = self.peek().value;
+			let next_token = self.peek()?;
 
-			if next_elem != "let"
-				&& next_elem != "if"
-				&& next_elem != "while"
-				&& next_elem != "do"
-				&& next_elem != "return"
+			if !next_token.is_keyword(Keyword::Constructor)
+				&& !next_token.is_keyword(Keyword::Function)
+				&& !next_token.is_keyword(Keyword::Method)
 			{
-				return result;
-			}
-
-			result.push_str(&self.parse_statement());
-		}
-	}
+				break;
+			};
 
-	fn parse_statement(&mut self) -> String {
-		match self.peek().value.as_str() {
-			"let" => self.parse_let_statement(),
-			"if" => self.parse_if_statement(),
-			"while" => self.parse_while_statement(),
-			"do" => self.parse_do_statement(),
-			"return" => self.parse_return_statement(),
-			_ => panic!("An error has occured"),
+			match self.parse_subroutine_dec() {
+				Ok(subroutine) => subroutines.push(subroutine),
+				Err(err) => self.recover(err, Parser::skip_class_member)?,
+			}
 		}
-	}
 
-	fn parse_let_statement(&mut self) -> String {
-		let mut result = String::new();
-		self.next(); // let
-		let var_name = self.parse_var_name();
+		self.expect(Symbol::RightBrace, "at end of class")?;
+		self.close("class");
 
-		let mut offset_code = String::new();
-
-		if self.peek().value == "[" {
-			self.next(); // [
-			offset_code.push_str(&self.parse_expression());
-			self.next(); // ]
+		// A file holds a single class
+		let end = self.peek()?;
+		if end.token != TokenType::EndOfFile {
+			return Err(Parser::unexpected("end of file after class", end));
 		}
 
-		self.next(); // =
-		result.push_str(&self.parse_expression());
-		self.next(); // ;
-
-		// Symbol of the assigned variable
-		let symbol = match self.find_symbol(&var_name) {
-			Some(sym) => sym,
-			None => panic!("An error has occured"),
-		};
+		Ok(Class {
+			name,
+			vars,
+			subroutines,
+			doc: class_token.doc.map(str::to_string),
+			span: self.span_from(&class_token.span),
+		})
+	}
 
-		if offset_code.len() != 0 {
-			if symbol.kind == "field" {
-				result.push_str(&format!("push this {}\n", symbol.index));
-			} else {
-				result.push_str(&format!("push {} {}\n", symbol.kind, symbol.index));
-			};
-			result.push_str(offset_code.as_str());
-			result.push_str("add\n");
-			result.push_str("pop pointer 1\n");
-			result.push_str("pop that 0\n");
+	fn parse_class_var_dec(&mut self) -> Result<ClassVarDec, CompileError> {
+		self.open("classVarDec");
+		let kind_token = self.next()?; // static or field
+		let kind = if kind_token.is_keyword(Keyword::Static) {
+			ClassVarKind::Static
 		} else {
-			if symbol.kind == "field" {
-				result.push_str(&format!("pop this {}\n", symbol.index));
-			} else {
-				result.push_str(&format!("pop {} {}\n", symbol.kind, symbol.index));
-			};
-		}
+			ClassVarKind::Field
+		};
+		let (typing, type_span) = self.parse_type()?; // int, char, boolean or class name
+		let names = self.parse_var_names()?;
+		self.close("classVarDec");
 
-		result
+		Ok(ClassVarDec {
+			kind,
+			typing,
+			type_span,
+			names,
+			doc: kind_token.doc.map(str::to_string),
+			span: self.span_from(&kind_token.span),
+		})
 	}
 
-	fn parse_if_statement(&mut self) -> String {
-		let mut result = String::new();
-		self.next(); // if
-		self.next(); // (
+	// One or more variable names separated by commas, then a `;`
+	fn parse_var_names(&mut self) -> Result<Vec<Identifier>, CompileError> {
+		let mut names = Vec::new();
 
-		result.push_str(&self.parse_expression());
-		result.push_str("not\n");
-		let label_false = self.get_label();
-		let label_true = self.get_label();
-		result.push_str(&format!("if-goto {}\n", label_false));
+		loop {
+			names.push(self.parse_identifier("variable name")?);
 
-		self.next(); // )
-		self.next(); // {
+			// Check for other variable declarations
+			let comma_or_semi = self.peek()?;
 
-		result.push_str(&self.parse_statements());
-		result.push_str(&format!("goto {}\n", label_true));
-		result.push_str(&format!("label {}\n", label_false));
-		self.next(); // }
-		if self.peek().value == "else" {
-			self.next(); // else
-			self.next(); // {
+			if comma_or_semi.is_symbol(Symbol::Semicolon) {
+				self.next()?; // ;
+				return Ok(names);
+			}
 
-			result.push_str(&self.parse_statements());
+			if !comma_or_semi.is_symbol(Symbol::Comma) {
+				return Err(Parser::unexpected("`,` or `;` after variable name", comma_or_semi));
+			}
 
-			self.next(); // }
+			self.next()?; // ,
 		}
-		result.push_str(&format!("label {}\n", label_true));
-
-		result
-	}
-
-	fn parse_while_statement(&mut self) -> String {
-		let mut result = String::new();
-		let loop_label = self.get_label();
-		let end_label = self.get_label();
-		result.push_str(&format!("label {}\n", loop_label));
-		self.next(); // while
-		self.next(); // (
-		result.push_str(&self.parse_expression());
-		result.push_str("not\n");
-		result.push_str(&format!("if-goto {}\n", end_label));
-		self.next(); // )
-		self.next(); // {
-		result.push_str(&self.parse_statements());
-		result.push_str(&format!("goto {}\n", loop_label));
-		result.push_str(&format!("label {}\n", end_label));
-		self.next(); // }
-		result
-	}
-
-	fn parse_do_statement(&mut self) -> String {
-		let mut result = String::new();
-		self.next(); // do
-		result.push_str(&self.parse_subroutine_call());
-		result.push_str("pop temp 0\n");
-		self.next(); // ;
-		result
-	}
-
-	fn parse_return_statement(&mut self) -> String {
-		let mut result = String::new();
-		self.next(); // return
-		if self.peek().value != ";" {
-			result.push_str(&self.parse_expression());
-		} else {
-			result.push_str("push constant 0\n");
+	}
+
+	fn parse_subroutine_dec(&mut self) -> Result<SubroutineDec, CompileError> {
+		self.open("subroutineDec");
+		let kind_token = self.next()?; // function, method or constructor
+		let kind = match kind_token.token {
+			TokenType::Keyword(Keyword::Constructor) => SubroutineKind::Constructor,
+			TokenType::Keyword(Keyword::Method) => SubroutineKind::Method,
+			_ => SubroutineKind::Function,
 		};
-		result.push_str("return\n");
-		self.next(); // ;
-		result
-	}
-
-	fn parse_subroutine_call(&mut self) -> String {
-		let mut result = String::new();
-		let func_or_class_name = self.next();
-		let mut function_name = String::new();
-		let mut param_count = 0;
-
-		match self.peek().value.as_str() {
-			"." => {
-				self.tokens.insert(0, func_or_class_name.clone());
-				let class_or_instance_name = self.parse_class_name();
-				self.next(); // .
-				let subroutine_name = self.parse_subroutine_name();
-
-				match self.find_symbol(&class_or_instance_name) {
-					Some(symbol) => {
-						match symbol.kind.as_str() {
-							"field" => result.push_str(&format!("push this {}\n", symbol.index)),
-							"argument" | "static" | "local" => {
-								result.push_str(&format!("push {} {}\n", symbol.kind, symbol.index))
-							}
-							_ => panic!("An error has occured"),
-						};
-						function_name.push_str(&format!("{}.{}", symbol.typing, subroutine_name));
-						param_count += 1;
-					}
-					None => {
-						function_name.push_str(&format!("{}.{}", class_or_instance_name, subroutine_name))
-					}
-				};
-			}
-			_ => {
-				param_count += 1;
-				result.push_str("push pointer 0\n");
-				function_name.push_str(&format!("{}.{}", self.class_name, func_or_class_name.value));
-			}
+
+		let (return_type, return_type_span) = if self.peek()?.is_keyword(Keyword::Void) {
+			(None, self.next()?.span) // void
+		} else {
+			let (typing, span) = self.parse_type()?;
+			(Some(typing), span)
 		};
 
-		self.next(); // (
+		let name = self.parse_identifier("subroutine name")?;
+		self.expect(Symbol::LeftParen, "after subroutine name")?;
 
-		if self.peek().value != ")" {
-			let (count, code) = self.parse_expression_list();
-			param_count += count;
-			result.push_str(&code);
-		};
+		let parameters = self.parse_parameter_list()?;
 
-		self.next(); // )
+		self.expect(Symbol::RightParen, "after parameter list")?;
 
-		result.push_str(&format!("call {} {}\n", function_name, param_count));
+		let body = self.parse_subroutine_body()?;
 
-		result
+		self.close("subroutineDec");
+		Ok(SubroutineDec {
+			kind,
+			return_type,
+			return_type_span,
+			name,
+			parameters,
+			body,
+			doc: kind_token.doc.map(str::to_string),
+			span: self.span_from(&kind_token.span),
+		})
 	}
 
-	fn parse_expression_list(&mut self) -> (u8, String) {
-		let mut result = String::new();
+	fn parse_parameter_list(&mut self) -> Result<Vec<Parameter>, CompileError> {
+		self.open("parameterList");
+		let mut parameters = Vec::new();
+		let type_or_else = self.peek()?;
 
-		let mut count = 1;
+		if !type_or_else.is_keyword(Keyword::Int)
+			&& !type_or_else.is_keyword(Keyword::Char)
+			&& !type_or_else.is_keyword(Keyword::Boolean)
+			&& type_or_else.token != TokenType::Identifier
+		{
+			self.close("parameterList");
+			return Ok(parameters);
+		};
 
 		loop {
-			result.push_str(&self.parse_expression());
-
-			if self.peek().value != "," {
-				return (count, result);
+			let start = self.peek()?.span;
+			let (typing, type_span) = self.parse_type()?;
+			let name = self.parse_identifier("variable name")?;
+
+			parameters.push(Parameter {
+				typing,
+				type_span,
+				name,
+				span: self.span_from(&start),
+			});
+
+			let comma_or_else = self.peek()?;
+
+			if !comma_or_else.is_symbol(Symbol::Comma) {
+				self.close("parameterList");
+				return Ok(parameters);
 			};
 
-			count += 1;
-
-			self.next(); // ,
+			self.next()?; // ,
 		}
 	}
 
-	fn parse_expression(&mut self) -> String {
-		let mut result = String::new();
-		result.push_str(&self.parse_term());
+	fn parse_subroutine_body(&mut self) -> Result<SubroutineBody, CompileError> {
+		self.open("subroutineBody");
+		let start = self.expect(Symbol::LeftBrace, "before subroutine body")?.span;
 
+		let mut vars = Vec::new();
 		loop {
-			let op_or_else = self.peek();
-
-			if op_or_else.value != "+"
-				&& op_or_else.value != "-"
-				&& op_or_else.value != "*"
-				&& op_or_else.value != "/"
-				&& op_or_else.value != "&"
-				&& op_or_else.value != "|"
-				&& op_or_else.value != "<"
-				&& op_or_else.value != ">"
-				&& op_or_else.value != "="
-				&& op_or_else.value != "~"
-			{
-				return result;
+			let var_or_else = self.peek()?;
+
+			if !var_or_else.is_keyword(Keyword::Var) {
+				break;
 			}
 
-			let op = self.parse_op();
-			result.push_str(&self.parse_term());
-
-			match op.as_str() {
-				"+" => result.push_str("add\n"),
-				"-" => result.push_str("sub\n"),
-				"*" => result.push_str("call Math.multiply 2\n"),
-				"/" => result.push_str("call Math.divide 2\n"),
-				"&" => result.push_str("and\n"),
-				"|" => result.push_str("or\n"),
-				"<" => result.push_str("lt\n"),
-				">" => result.push_str("gt\n"),
-				"=" => result.push_str("eq\n"),
-				"~" => result.push_str("neg\n"),
-				_ => panic!("An error has occured"),
+			match self.parse_var_dec() {
+				Ok(var_dec) => vars.push(var_dec),
+				Err(err) => self.recover(err, Parser::skip_statement)?,
 			}
 		}
+
+		let statements = self.parse_statements()?;
+
+		self.expect(Symbol::RightBrace, "at end of subroutine body")?;
+		self.close("subroutineBody");
+		Ok(SubroutineBody {
+			vars,
+			statements,
+			span: self.span_from(&start),
+		})
 	}
 
-	fn parse_op(&mut self) -> String {
-		self.next().value
+	fn parse_var_dec(&mut self) -> Result<VarDec, CompileError> {
+		self.open("varDec");
+		let start = self.next()?.span; // var
+		let (typing, type_span) = self.parse_type()?;
+		let names = self.parse_var_names()?;
+		self.close("varDec");
+
+		Ok(VarDec {
+			typing,
+			type_span,
+			names,
+			span: self.span_from(&start),
+		})
 	}
 
-	fn parse_term(&mut self) -> String {
-		let mut result = String::new();
+	fn parse_statements(&mut self) -> Result<Vec<Statement>, CompileError> {
+		self.open("statements");
+		let mut statements = Vec::new();
 
-		let next_token = self.peek();
+		loop {
+			let next_token = self.peek()?;
 
-		if next_token.token == TokenType::IntegerConstant {
-			let integer_constant = self.parse_integer_constant();
-			result.push_str(&format!("push constant {}\n", integer_constant));
-			return result;
-		};
-		if next_token.token == TokenType::StringConstant {
-			let string_constant = self.parse_string_constant();
-			result.push_str(&format!("push constant {}\n", string_constant.len()));
-			result.push_str(&format!("call String.new 1\n"));
-
-			for c in string_constant.chars() {
-				result.push_str(&format!("push constant {}\n", c as u8));
-				result.push_str(&format!("call String.appendChar 2\n"));
+			// The end of the statements is checked by the caller
+			if next_token.token == TokenType::EndOfFile
+				|| next_token.is_symbol(Symbol::RightBrace)
+				|| starts_class_member(&next_token)
+			{
+				self.close("statements");
+				return Ok(statements);
 			}
 
-			return result;
-		};
-		if next_token.value == "true"
-			|| next_token.value == "false"
-			|| next_token.value == "null"
-			|| next_token.value == "this"
-		{
-			let keyword_constant = self.parse_keyword_constant();
-
-			match keyword_constant.as_str() {
-				"true" => {
-					result.push_str("push constant 0\n");
-					result.push_str("not\n")
+			let statement = match next_token.token {
+				TokenType::Keyword(Keyword::Let) => self.parse_let_statement().map(Statement::Let),
+				TokenType::Keyword(Keyword::If) => self.parse_if_statement().map(Statement::If),
+				TokenType::Keyword(Keyword::While) => {
+					self.parse_while_statement().map(Statement::While)
 				}
-				"false" => result.push_str("push constant 0\n"),
-				"null" => result.push_str("push constant 0\n"),
-				"this" => result.push_str("push pointer 0\n"),
-				_ => panic!("An error has occured"),
+				TokenType::Keyword(Keyword::Do) => self.parse_do_statement().map(Statement::Do),
+				TokenType::Keyword(Keyword::Return) => {
+					self.parse_return_statement().map(Statement::Return)
+				}
+				_ => Err(Parser::unexpected("a statement", next_token)),
+			};
+
+			match statement {
+				Ok(statement) => statements.push(statement),
+				Err(err) => self.recover(err, Parser::skip_statement)?,
 			}
+		}
+	}
 
-			return result;
-		};
+	// Statements between braces, as the body of an `if`, `else` or `while`
+	fn parse_block(&mut self, name: &str) -> Result<Vec<Statement>, CompileError> {
+		self.expect(Symbol::LeftBrace, &format!("before {} body", name))?;
+		let statements = self.parse_statements()?;
+		self.expect(Symbol::RightBrace, &format!("at end of {} body", name))?;
+		Ok(statements)
+	}
 
-		// (expression)
-		if next_token.value == "(" {
-			self.next(); // (
-			result.push_str(&self.parse_expression());
-			self.next(); // )
-			return result;
-		};
+	fn parse_let_statement(&mut self) -> Result<LetStatement, CompileError> {
+		self.open("letStatement");
+		let start = self.next()?.span; // let
+		let name = self.parse_identifier("variable name")?;
 
-		// unary_op term
-		if next_token.value == "-" || next_token.value == "~" {
-			let unary_op = self.parse_unary_op();
+		let mut index = None;
+		if self.peek()?.is_symbol(Symbol::LeftBracket) {
+			self.next()?; // [
+			index = Some(self.parse_expression()?);
+			self.expect(Symbol::RightBracket, "after array index")?;
+		}
 
-			result.push_str(&self.parse_term());
+		self.expect(Symbol::Equals, "in let statement")?;
+		let value = self.parse_expression()?;
+		self.expect(Symbol::Semicolon, "after let statement")?;
 
-			match &unary_op[..] {
-				"-" => result.push_str("neg\n"),
-				"~" => result.push_str("not\n"),
-				_ => panic!("An error has occured"),
-			};
+		self.close("letStatement");
+		Ok(LetStatement {
+			name,
+			index,
+			value,
+			span: self.span_from(&start),
+		})
+	}
 
-			return result;
-		};
+	fn parse_if_statement(&mut self) -> Result<IfStatement, CompileError> {
+		self.open("ifStatement");
+		let start = self.next()?.span; // if
+		self.expect(Symbol::LeftParen, "after `if`")?;
+		let condition = self.parse_expression()?;
+		self.expect(Symbol::RightParen, "after if condition")?;
 
-		// Var name or subroutine call
-		let var_name_or_sub_name = self.next();
+		let statements = self.parse_block("if")?;
 
-		let bracket_or_else = self.peek();
+		let mut else_statements = None;
+		if self.peek()?.is_keyword(Keyword::Else) {
+			self.next()?; // else
+			else_statements = Some(self.parse_block("else")?);
+		}
 
-		// Var[]
-		if bracket_or_else.value == "[" {
-			let var_name = var_name_or_sub_name.value;
+		self.close("ifStatement");
+		Ok(IfStatement {
+			condition,
+			statements,
+			else_statements,
+			span: self.span_from(&start),
+		})
+	}
 
-			self.next(); // [
-			result.push_str(&self.parse_expression());
+	fn parse_while_statement(&mut self) -> Result<WhileStatement, CompileError> {
+		self.open("whileStatement");
+		let start = self.next()?.span; // while
+		self.expect(Symbol::LeftParen, "after `while`")?;
+		let condition = self.parse_expression()?;
+		self.expect(Symbol::RightParen, "after while condition")?;
 
-			let symbol = match self.find_symbol(&var_name) {
-				Some(sym) => sym,
-				None => panic!("An error has occured"),
-			};
+		let statements = self.parse_block("while")?;
 
-			match symbol.kind.as_str() {
-				"field" => result.push_str(&format!("push this {}\n", symbol.index)),
-				"argument" | "static" | "local" => {
-					result.push_str(&format!("push {} {}\n", symbol.kind, symbol.index))
-				}
-				_ => panic!("An error has occured"),
-			};
+		self.close("whileStatement");
+		Ok(WhileStatement {
+			condition,
+			statements,
+			span: self.span_from(&start),
+		})
+	}
 
-			result.push_str("add\n");
-			result.push_str("pop pointer 1\n");
-			result.push_str("push that 0\n");
-			self.next(); // ]
-			return result;
-		};
+	fn parse_do_statement(&mut self) -> Result<DoStatement, CompileError> {
+		self.open("doStatement");
+		let start = self.next()?.span; // do
+		let call = self.parse_subroutine_call()?;
+		self.expect(Symbol::Semicolon, "after do statement")?;
 
-		// Subroutine
-		if bracket_or_else.value == "(" || bracket_or_else.value == "." {
-			self.tokens.insert(0, var_name_or_sub_name);
-			result.push_str(&self.parse_subroutine_call());
-			return result;
-		};
-
-		// Var name
-		self.tokens.insert(0, var_name_or_sub_name);
+		self.close("doStatement");
+		Ok(DoStatement {
+			call,
+			span: self.span_from(&start),
+		})
+	}
 
-		let var_name = self.parse_var_name();
+	fn parse_return_statement(&mut self) -> Result<ReturnStatement, CompileError> {
+		self.open("returnStatement");
+		let start = self.next()?.span; // return
 
-		let symbol = match self.find_symbol(&var_name) {
-			Some(sym) => sym,
-			None => panic!("An error has occured"),
-		};
-		match symbol.kind.as_str() {
-			"field" => result.push_str(&format!("push this {}\n", symbol.index)),
-			"argument" | "static" | "local" => {
-				result.push_str(&format!("push {} {}\n", symbol.kind, symbol.index))
-			}
-			_ => panic!("An error has occured"),
-		};
+		let mut value = None;
+		if !self.peek()?.is_symbol(Symbol::Semicolon) {
+			value = Some(self.parse_expression()?);
+		}
+		self.expect(Symbol::Semicolon, "after return statement")?;
 
-		return result;
+		self.close("returnStatement");
+		Ok(ReturnStatement {
+			value,
+			span: self.span_from(&start),
+		})
 	}
 
-	fn parse_unary_op(&mut self) -> String {
-		self.next().value
-	}
+	fn parse_subroutine_call(&mut self) -> Result<SubroutineCall, CompileError> {
+		let start = self.peek()?.span;
+		let mut receiver = None;
 
-	fn parse_integer_constant(&mut self) -> String {
-		self.next().value
-	}
+		// Class name or variable
+		if self.peek_nth(1)?.is_symbol(Symbol::Dot) {
+			receiver = Some(self.parse_identifier("class or variable name")?);
+			self.next()?; // .
+		}
 
-	fn parse_string_constant(&mut self) -> String {
-		self.next().value
-	}
+		let name = self.parse_identifier("subroutine name")?;
+		self.expect(Symbol::LeftParen, "after subroutine name")?;
+		let arguments = self.parse_expression_list()?;
+		self.expect(Symbol::RightParen, "after arguments")?;
 
-	fn parse_keyword_constant(&mut self) -> String {
-		self.next().value
+		Ok(SubroutineCall {
+			receiver,
+			name,
+			arguments,
+			span: self.span_from(&start),
+		})
 	}
 
-	fn parse_parameter_list(&mut self) {
-		let type_or_else = self.peek();
+	fn parse_expression_list(&mut self) -> Result<Vec<Expression>, CompileError> {
+		self.open("expressionList");
+		let mut expressions = Vec::new();
 
-		if type_or_else.value != "int"
-			&& type_or_else.value != "char"
-			&& type_or_else.value != "boolean"
-			&& type_or_else.token != TokenType::Identifier
-		{
-			return;
-		};
+		if self.peek()?.is_symbol(Symbol::RightParen) {
+			self.close("expressionList");
+			return Ok(expressions);
+		}
 
 		loop {
-			let typing = self.next().value;
-			let name = self.parse_var_name();
+			expressions.push(self.parse_expression()?);
 
-			self.add_symbol_in_func(&name, &"argument".to_string(), &typing);
+			if !self.peek()?.is_symbol(Symbol::Comma) {
+				self.close("expressionList");
+				return Ok(expressions);
+			};
+
+			self.next()?; // ,
+		}
+	}
 
-			let comma_or_else = self.peek();
+	fn parse_expression(&mut self) -> Result<Expression, CompileError> {
+		self.open("expression");
+		let start = self.peek()?.span;
+		let term = self.parse_term()?;
+		let mut operations = Vec::new();
 
-			if comma_or_else.value != "," {
-				return;
+		loop {
+			let op = match self.peek()?.token {
+				TokenType::Symbol(Symbol::Plus) => BinaryOp::Add,
+				TokenType::Symbol(Symbol::Minus) => BinaryOp::Subtract,
+				TokenType::Symbol(Symbol::Asterisk) => BinaryOp::Multiply,
+				TokenType::Symbol(Symbol::Slash) => BinaryOp::Divide,
+				TokenType::Symbol(Symbol::Ampersand) => BinaryOp::And,
+				TokenType::Symbol(Symbol::Pipe) => BinaryOp::Or,
+				TokenType::Symbol(Symbol::LessThan) => BinaryOp::LessThan,
+				TokenType::Symbol(Symbol::GreaterThan) => BinaryOp::GreaterThan,
+				TokenType::Symbol(Symbol::Equals) => BinaryOp::Equal,
+				_ => {
+					self.close("expression");
+					return Ok(Expression {
+						term: Box::new(term),
+						operations,
+						span: self.span_from(&start),
+					});
+				}
 			};
 
-			self.next(); // ,
+			self.next()?; // op
+			operations.push((op, self.parse_term()?));
 		}
 	}
 
-	fn parse_class_var_dec(&mut self) {
-		let kind = self.next().value; // static or field
-		let typing = self.parse_type(); // int, char, boolean or class name
+	fn parse_term(&mut self) -> Result<Term, CompileError> {
+		self.open("term");
+		let term = self.parse_term_content()?;
+		self.close("term");
+		Ok(term)
+	}
 
-		loop {
-			let name = self.parse_var_name();
+	fn parse_term_content(&mut self) -> Result<Term, CompileError> {
+		let next_token = self.peek()?;
 
-			self.add_symbol_in_class(&name, &kind, &typing);
+		match next_token.token {
+			TokenType::IntegerConstant => {
+				self.next()?; // integer constant
+				// The tokenizer only reads integers up to 32767
+				let value = next_token.value.parse().unwrap_or(u16::MAX);
+				return Ok(Term::IntegerConstant {
+					value,
+					span: next_token.span,
+				});
+			}
+			TokenType::StringConstant => {
+				self.next()?; // string constant
+				return Ok(Term::StringConstant {
+					value: next_token.value.to_string(),
+					span: next_token.span,
+				});
+			}
+			TokenType::Keyword(keyword) => {
+				let constant = match keyword {
+					Keyword::True => KeywordConstant::True,
+					Keyword::False => KeywordConstant::False,
+					Keyword::Null => KeywordConstant::Null,
+					Keyword::This => KeywordConstant::This,
+					_ => return Err(Parser::expected_term(next_token)),
+				};
 
-			// Check for other variable declarations
-			let comma_or_semi = self.next();
+				self.next()?; // keyword constant
+				return Ok(Term::KeywordConstant {
+					value: constant,
+					span: next_token.span,
+				});
+			}
+			// (expression)
+			TokenType::Symbol(Symbol::LeftParen) => {
+				self.next()?; // (
+				let expression = self.parse_expression()?;
+				self.expect(Symbol::RightParen, "after expression")?;
+				let span = self.span_from(&next_token.span);
+				return Ok(Term::Parenthesized {
+					expression: Box::new(expression),
+					span,
+				});
+			}
+			// unary_op term
+			TokenType::Symbol(symbol) => {
+				let op = match symbol {
+					Symbol::Minus => UnaryOp::Negate,
+					Symbol::Tilde => UnaryOp::Not,
+					_ => return Err(Parser::expected_term(next_token)),
+				};
 
-			if comma_or_semi.value == ";" {
-				return;
+				self.next()?; // unary op
+				let term = self.parse_term()?;
+				let span = self.span_from(&next_token.span);
+				return Ok(Term::Unary {
+					op,
+					term: Box::new(term),
+					span,
+				});
 			}
+			_ => {}
 		}
-	}
 
-	fn parse_var_name(&mut self) -> String {
-		self.next().value
+		// Var name or subroutine call
+		let bracket_or_else = self.peek_nth(1)?;
+
+		// Var[]
+		if bracket_or_else.is_symbol(Symbol::LeftBracket) {
+			let name = self.parse_identifier("variable name")?;
+			self.next()?; // [
+			let index = self.parse_expression()?;
+			self.expect(Symbol::RightBracket, "after array index")?;
+			let span = self.span_from(&next_token.span);
+			return Ok(Term::ArrayAccess {
+				name,
+				index: Box::new(index),
+				span,
+			});
+		};
+
+		// Subroutine
+		if bracket_or_else.is_symbol(Symbol::LeftParen) || bracket_or_else.is_symbol(Symbol::Dot) {
+			return Ok(Term::SubroutineCall(self.parse_subroutine_call()?));
+		};
+
+		// Var name
+		Ok(Term::Variable(self.parse_identifier("variable name")?))
 	}
 
-	fn parse_type(&mut self) -> String {
-		let front_token = self.peek();
+	// A type and where it is written
+	fn parse_type(&mut self) -> Result<(Type, Span), CompileError> {
+		let front_token = self.peek()?;
 
-		if front_token.value == "int" || front_token.value == "char" || front_token.value == "boolean" {
-			self.next().value
-		} else {
-			self.parse_class_name()
-		}
+		let typing = match front_token.token {
+			TokenType::Keyword(Keyword::Int) => Type::Int,
+			TokenType::Keyword(Keyword::Char) => Type::Char,
+			TokenType::Keyword(Keyword::Boolean) => Type::Boolean,
+			TokenType::Identifier => Type::Class(front_token.value.to_string()),
+			_ => return Err(Parser::unexpected("a type", front_token)),
+		};
+
+		let span = self.next()?.span; // type
+		Ok((typing, span))
 	}
 
-	fn parse_class_name(&mut self) -> String {
-		self.next().value
+	// An identifier naming `what`
+	fn parse_identifier(&mut self, what: &str) -> Result<Identifier, CompileError> {
+		let token = self.expect_identifier(what)?;
+
+		Ok(Identifier {
+			name: token.value.to_string(),
+			span: token.span,
+		})
 	}
 
-	pub fn new(tokens: VecDeque<Token>) -> Parser {
+	/// Creates a parser pulling its tokens from the given stream, typically
+	/// a `Tokenizer`.
+	pub fn new<T>(tokens: T) -> Parser<'a>
+	where
+		T: IntoIterator<Item = Token<'a>>,
+		T::IntoIter: 'a,
+	{
 		Parser {
-			tokens: tokens,
-			class_name: String::new(),
-			class_symbol_table: HashMap::new(),
-			func_symbol_table: HashMap::new(),
-			label_count: 0,
+			tokens: Box::new(tokens.into_iter()),
+			lookahead: VecDeque::new(),
+			xml: None,
+			last_span: Span::new(Rc::from(""), Position::start(), Position::start()),
+			previous_span: None,
+			errors: Vec::new(),
+			error_limit: DEFAULT_ERROR_LIMIT,
 		}
 	}
 
-	pub fn parse(&mut self) -> String {
-		self.parse_class()
+	/// Sets how many errors are reported before parsing stops. With a limit
+	/// of 0 or 1, parsing stops at the first error.
+	pub fn set_error_limit(&mut self, limit: usize) {
+		self.error_limit = limit;
+	}
+
+	/// Parses the class and returns its syntax tree, or every error found in
+	/// the class, in source order, up to the error limit
+	pub fn parse(&mut self) -> Result<Class, Vec<CompileError>> {
+		let result = self.parse_class();
+		self.finish(result)
+	}
+
+	/// Parses the class and returns its parse tree in the format of the
+	/// nand2tetris analyzer (`xxx.xml`) rather than its syntax tree
+	pub fn parse_xml(&mut self) -> Result<String, Vec<CompileError>> {
+		self.xml = Some(XmlWriter::new());
+		let result = self.parse_class();
+		let xml = self.xml.take().unwrap().finish();
+		self.finish(result).map(|_| xml)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::tokenizer::Tokenizer;
+
+	// A class with independent errors on lines 2, 4, 5, 6 and 10
+	const ERRORS: &str = "class Main {
+	field int 1;
+	function void main() {
+		if (x { let a = 1; }
+		let b = ;
+		do Output.printInt(1;
+		return;
+	}
+	method void f() {
+		let = 3;
+	}
+}
+";
+
+	// The line and message of every error of the class
+	fn errors(code: &str, limit: usize) -> Vec<(usize, String)> {
+		let mut parser = Parser::new(Tokenizer::new(code, "Main.jack"));
+		parser.set_error_limit(limit);
+
+		parser
+			.parse()
+			.unwrap_err()
+			.into_iter()
+			.map(|err| (err.span.start.line, err.message))
+			.collect()
+	}
+
+	#[test]
+	fn independent_errors_in_source_order() {
+		let expected = vec![
+			(2, "expected variable name, found `1`"),
+			(4, "expected `)` after if condition, found `{`"),
+			(5, "expected an expression, found `;`"),
+			(6, "expected `)` after arguments, found `;`"),
+			(10, "expected variable name, found `=`"),
+		];
+		let expected: Vec<(usize, String)> = expected
+			.into_iter()
+			.map(|(line, message)| (line, message.to_string()))
+			.collect();
+
+		assert_eq!(errors(ERRORS, DEFAULT_ERROR_LIMIT), expected);
+	}
+
+	#[test]
+	fn missing_semicolon_before_brace() {
+		let code = "class Main {
+	function void main() {
+		var int x;
+		let x = 1
+	}
+}
+";
+
+		assert_eq!(
+			errors(code, DEFAULT_ERROR_LIMIT),
+			vec![(5, "expected `;` after let statement, found `}`".to_string())]
+		);
+	}
+
+	#[test]
+	fn last_error_at_end_of_file() {
+		let code = "class Main { function void main() { let x = ; ";
+
+		assert_eq!(
+			errors(code, DEFAULT_ERROR_LIMIT),
+			vec![(1, "expected an expression, found `;`".to_string())]
+		);
+	}
+
+	#[test]
+	fn error_limit() {
+		let lines = |errors: Vec<(usize, String)>| -> Vec<usize> {
+			errors.into_iter().map(|(line, _)| line).collect()
+		};
+
+		assert_eq!(lines(errors(ERRORS, 1)), vec![2]);
+		assert_eq!(lines(errors(ERRORS, 3)), vec![2, 4, 5]);
 	}
 }
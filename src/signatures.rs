@@ -0,0 +1,247 @@
+use crate::ast::{Class, SubroutineKind, Type};
+use crate::span::Span;
+
+use std::collections::HashMap;
+
+use SubroutineKind::*;
+
+// The subroutines of the Jack OS: class, kind, return type, name and
+// parameter types
+#[rustfmt::skip]
+const OS_SUBROUTINES: &[(&str, SubroutineKind, &str, &str, &[&str])] = &[
+	("Math", Function, "void", "init", &[]),
+	("Math", Function, "int", "abs", &["int"]),
+	("Math", Function, "int", "multiply", &["int", "int"]),
+	("Math", Function, "int", "divide", &["int", "int"]),
+	("Math", Function, "int", "min", &["int", "int"]),
+	("Math", Function, "int", "max", &["int", "int"]),
+	("Math", Function, "int", "sqrt", &["int"]),
+	("String", Constructor, "String", "new", &["int"]),
+	("String", Method, "void", "dispose", &[]),
+	("String", Method, "int", "length", &[]),
+	("String", Method, "char", "charAt", &["int"]),
+	("String", Method, "void", "setCharAt", &["int", "char"]),
+	("String", Method, "String", "appendChar", &["char"]),
+	("String", Method, "void", "eraseLastChar", &[]),
+	("String", Method, "int", "intValue", &[]),
+	("String", Method, "void", "setInt", &["int"]),
+	("String", Function, "char", "backSpace", &[]),
+	("String", Function, "char", "doubleQuote", &[]),
+	("String", Function, "char", "newLine", &[]),
+	("Array", Function, "Array", "new", &["int"]),
+	("Array", Method, "void", "dispose", &[]),
+	("Output", Function, "void", "init", &[]),
+	("Output", Function, "void", "moveCursor", &["int", "int"]),
+	("Output", Function, "void", "printChar", &["char"]),
+	("Output", Function, "void", "printString", &["String"]),
+	("Output", Function, "void", "printInt", &["int"]),
+	("Output", Function, "void", "println", &[]),
+	("Output", Function, "void", "backSpace", &[]),
+	("Screen", Function, "void", "init", &[]),
+	("Screen", Function, "void", "clearScreen", &[]),
+	("Screen", Function, "void", "setColor", &["boolean"]),
+	("Screen", Function, "void", "drawPixel", &["int", "int"]),
+	("Screen", Function, "void", "drawLine", &["int", "int", "int", "int"]),
+	("Screen", Function, "void", "drawRectangle", &["int", "int", "int", "int"]),
+	("Screen", Function, "void", "drawCircle", &["int", "int", "int"]),
+	("Keyboard", Function, "void", "init", &[]),
+	("Keyboard", Function, "char", "keyPressed", &[]),
+	("Keyboard", Function, "char", "readChar", &[]),
+	("Keyboard", Function, "String", "readLine", &["String"]),
+	("Keyboard", Function, "int", "readInt", &["String"]),
+	("Memory", Function, "void", "init", &[]),
+	("Memory", Function, "int", "peek", &["int"]),
+	("Memory", Function, "void", "poke", &["int", "int"]),
+	("Memory", Function, "Array", "alloc", &["int"]),
+	("Memory", Function, "void", "deAlloc", &["Array"]),
+	("Sys", Function, "void", "init", &[]),
+	("Sys", Function, "void", "halt", &[]),
+	("Sys", Function, "void", "error", &["int"]),
+	("Sys", Function, "void", "wait", &["int"]),
+];
+
+/// A subroutine as seen by its callers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+	pub class_name: String,
+	pub kind: SubroutineKind,
+	// `None` for `void`
+	pub return_type: Option<Type>,
+	pub name: String,
+	pub parameters: Vec<Type>,
+	// Where the subroutine is declared, `None` for the OS
+	pub span: Option<Span>,
+}
+
+impl Signature {
+	/// The name of the subroutine in the VM code, as in `Math.abs`
+	pub fn full_name(&self) -> String {
+		format!("{}.{}", self.class_name, self.name)
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+struct ClassSignatures {
+	subroutines: HashMap<String, Signature>,
+	// False when the subroutines of the class are not known, as for a class
+	// that could not be parsed
+	complete: bool,
+}
+
+/// The subroutines of every class of a program, collected before compiling
+/// any of them so that calls to other classes can be checked
+#[derive(Debug, Clone, Default)]
+pub struct SignatureTable {
+	classes: HashMap<String, ClassSignatures>,
+}
+
+impl SignatureTable {
+	pub fn new() -> SignatureTable {
+		SignatureTable::default()
+	}
+
+	/// A table holding the classes of the Jack OS
+	pub fn with_os() -> SignatureTable {
+		let mut table = SignatureTable::new();
+
+		for (class_name, kind, return_type, name, parameters) in OS_SUBROUTINES {
+			let return_type = match *return_type {
+				"void" => None,
+				typing => Some(Type::from(typing.to_string())),
+			};
+			let signature = Signature {
+				class_name: class_name.to_string(),
+				kind: *kind,
+				return_type,
+				name: name.to_string(),
+				parameters: parameters
+					.iter()
+					.map(|typing| Type::from(typing.to_string()))
+					.collect(),
+				span: None,
+			};
+
+			let class = table.classes.entry(class_name.to_string()).or_default();
+			class.complete = true;
+			class.subroutines.insert(name.to_string(), signature);
+		}
+
+		table
+	}
+
+	/// Adds the subroutines of a class, in place of those of an OS class with
+	/// the same name, as programs may implement the OS themselves. Of two
+	/// subroutines with the same name, the first one is kept.
+	pub fn add_class(&mut self, class: &Class) {
+		let mut signatures = ClassSignatures {
+			subroutines: HashMap::new(),
+			complete: true,
+		};
+
+		for subroutine in &class.subroutines {
+			let signature = Signature {
+				class_name: class.name.name.clone(),
+				kind: subroutine.kind,
+				return_type: subroutine.return_type.clone(),
+				name: subroutine.name.name.clone(),
+				parameters: subroutine
+					.parameters
+					.iter()
+					.map(|parameter| parameter.typing.clone())
+					.collect(),
+				span: Some(subroutine.name.span.clone()),
+			};
+
+			signatures
+				.subroutines
+				.entry(signature.name.clone())
+				.or_insert(signature);
+		}
+
+		self.classes.insert(class.name.name.clone(), signatures);
+	}
+
+	/// Adds a class whose subroutines are not known, calls to it being
+	/// assumed to be right. Replaces an OS class with the same name.
+	pub fn declare_class(&mut self, name: &str) {
+		self.classes
+			.insert(name.to_string(), ClassSignatures::default());
+	}
+
+	pub fn has_class(&self, name: &str) -> bool {
+		self.classes.contains_key(name)
+	}
+
+	/// Whether every subroutine of the class is known, so that calling any
+	/// other one is an error
+	pub fn is_complete(&self, class_name: &str) -> bool {
+		self.classes
+			.get(class_name)
+			.is_some_and(|class| class.complete)
+	}
+
+	pub fn get(&self, class_name: &str, name: &str) -> Option<&Signature> {
+		self.classes
+			.get(class_name)
+			.and_then(|class| class.subroutines.get(name))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::codegen::CodeGenerator;
+	use crate::parser::Parser;
+	use crate::tokenizer::Tokenizer;
+
+	use std::rc::Rc;
+
+	fn parse(code: &str, file: &str) -> Class {
+		Parser::new(Tokenizer::new(code, file)).parse().unwrap()
+	}
+
+	#[test]
+	fn program_class_replaces_os_class() {
+		let math = parse(
+			"class Math { function void init(int size) { return; } }",
+			"Math.jack",
+		);
+		let mut table = SignatureTable::with_os();
+		table.add_class(&math);
+
+		let init = table.get("Math", "init").unwrap();
+		assert_eq!(init.parameters, vec![Type::Int]);
+		assert!(init.span.is_some());
+		assert!(table.get("Math", "abs").is_none());
+		assert!(table.get("Output", "printInt").is_some());
+	}
+
+	#[test]
+	fn calls_to_overridden_os_class_compile() {
+		let math = parse(
+			"class Math { function void init(int size) { return; } }",
+			"Math.jack",
+		);
+		let main = parse(
+			"class Main { function void main() { do Math.init(16); return; } }",
+			"Main.jack",
+		);
+		let mut table = SignatureTable::with_os();
+		table.add_class(&math);
+		table.add_class(&main);
+
+		let mut generator = CodeGenerator::new();
+		generator.set_signatures(Rc::new(table));
+		assert!(generator.generate(&main).is_ok());
+	}
+
+	#[test]
+	fn unparsed_class_replaces_os_class() {
+		let mut table = SignatureTable::with_os();
+		table.declare_class("Screen");
+
+		assert!(table.has_class("Screen"));
+		assert!(!table.is_complete("Screen"));
+		assert!(table.get("Screen", "drawPixel").is_none());
+	}
+}
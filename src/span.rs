@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::rc::Rc;
+
+/// A location in a source file. Lines and columns start at 1, the offset is
+/// the number of bytes from the beginning of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+	pub line: usize,
+	pub column: usize,
+	pub offset: usize,
+}
+
+impl Position {
+	pub fn start() -> Position {
+		Position {
+			line: 1,
+			column: 1,
+			offset: 0,
+		}
+	}
+
+	// Moves the position past the given text
+	pub fn advance(&mut self, text: &str) {
+		for c in text.chars() {
+			if c == '\n' {
+				self.line += 1;
+				self.column = 1;
+			} else {
+				self.column += 1;
+			}
+		}
+		self.offset += text.len();
+	}
+}
+
+/// The region of a source file a token (or any construct built from tokens)
+/// was read from. `start` is inclusive and `end` is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+	pub file: Rc<str>,
+	pub start: Position,
+	pub end: Position,
+}
+
+impl Span {
+	pub fn new(file: Rc<str>, start: Position, end: Position) -> Span {
+		Span { file, start, end }
+	}
+
+	/// The byte range of the span in its source file
+	pub fn range(&self) -> std::ops::Range<usize> {
+		self.start.offset..self.end.offset
+	}
+}
+
+impl fmt::Display for Span {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}:{}", self.file, self.start.line, self.start.column)
+	}
+}
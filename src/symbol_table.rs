@@ -0,0 +1,244 @@
+use crate::ast::{Class, ClassVarKind, Identifier, SubroutineDec, SubroutineKind, Type};
+use crate::error::{CompileError, ErrorKind};
+use crate::span::Span;
+
+use std::collections::HashMap;
+use strum_macros::Display;
+
+/// Where a variable is stored, each kind having its own VM segment
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[strum(serialize_all = "lowercase")]
+pub enum SymbolKind {
+	Static,
+	Field,
+	Argument,
+	Local,
+}
+
+impl SymbolKind {
+	/// The VM segment holding the variables of this kind. Fields are read
+	/// through `this`, which points to the current object.
+	pub fn segment(self) -> &'static str {
+		match self {
+			SymbolKind::Static => "static",
+			SymbolKind::Field => "this",
+			SymbolKind::Argument => "argument",
+			SymbolKind::Local => "local",
+		}
+	}
+}
+
+impl From<ClassVarKind> for SymbolKind {
+	fn from(kind: ClassVarKind) -> SymbolKind {
+		match kind {
+			ClassVarKind::Static => SymbolKind::Static,
+			ClassVarKind::Field => SymbolKind::Field,
+		}
+	}
+}
+
+/// A declared variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+	pub name: String,
+	pub typing: Type,
+	pub kind: SymbolKind,
+	// The index of the variable in its segment
+	pub index: u16,
+	// Where the variable is declared
+	pub span: Span,
+}
+
+/// The variables of a scope, either a class or a subroutine, numbered in
+/// their segment in the order they are declared
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+	symbols: HashMap<String, Symbol>,
+	// The number of variables declared so far, by kind
+	counts: HashMap<SymbolKind, u16>,
+}
+
+impl SymbolTable {
+	pub fn new() -> SymbolTable {
+		SymbolTable::default()
+	}
+
+	// Declares a variable written in the code, recording an error when its
+	// name is already taken in the scope
+	fn declare(
+		&mut self,
+		name: &Identifier,
+		typing: &Type,
+		kind: SymbolKind,
+		errors: &mut Vec<CompileError>,
+	) {
+		let previous = match self.get(&name.name) {
+			Some(previous) => previous,
+			None => {
+				if let Err(err) = self.define(&name.name, typing.clone(), kind, name.span.clone()) {
+					errors.push(err);
+				}
+				return;
+			}
+		};
+
+		let err = if kind == SymbolKind::Local && previous.kind == SymbolKind::Argument {
+			CompileError::new(
+				ErrorKind::DuplicateDeclaration,
+				format!(
+					"local variable `{}` has the same name as a parameter",
+					name.name
+				),
+				name.span.clone(),
+			)
+			.with_label(previous.span.clone(), "parameter declared here")
+		} else {
+			CompileError::new(
+				ErrorKind::DuplicateDeclaration,
+				format!("`{}` is declared twice", name.name),
+				name.span.clone(),
+			)
+			.with_label(previous.span.clone(), "first declared here")
+		};
+
+		errors.push(err);
+	}
+
+	/// The static and field variables of a class. Names declared twice are
+	/// reported in `errors`, the first declaration being kept.
+	pub fn for_class(class: &Class, errors: &mut Vec<CompileError>) -> SymbolTable {
+		let mut table = SymbolTable::new();
+
+		for var_dec in &class.vars {
+			for name in &var_dec.names {
+				table.declare(name, &var_dec.typing, var_dec.kind.into(), errors);
+			}
+		}
+
+		table
+	}
+
+	/// The arguments and local variables of a subroutine of the given class,
+	/// methods taking the object as their first argument, `this`. Names
+	/// declared twice are reported in `errors`, the first declaration being
+	/// kept.
+	pub fn for_subroutine(
+		class_name: &str,
+		subroutine: &SubroutineDec,
+		errors: &mut Vec<CompileError>,
+	) -> SymbolTable {
+		let mut table = SymbolTable::new();
+
+		if subroutine.kind == SubroutineKind::Method {
+			let this = table.define(
+				"this",
+				Type::Class(class_name.to_string()),
+				SymbolKind::Argument,
+				subroutine.name.span.clone(),
+			);
+			if let Err(err) = this {
+				errors.push(err);
+			}
+		}
+
+		for parameter in &subroutine.parameters {
+			table.declare(
+				&parameter.name,
+				&parameter.typing,
+				SymbolKind::Argument,
+				errors,
+			);
+		}
+
+		for var_dec in &subroutine.body.vars {
+			for name in &var_dec.names {
+				table.declare(name, &var_dec.typing, SymbolKind::Local, errors);
+			}
+		}
+
+		table
+	}
+
+	/// Declares a variable with the next index of its kind. Returns the
+	/// variable previously declared with the same name, if any, or an error
+	/// when the segment of the kind is full.
+	pub fn define(
+		&mut self,
+		name: &str,
+		typing: Type,
+		kind: SymbolKind,
+		span: Span,
+	) -> Result<Option<Symbol>, CompileError> {
+		let count = self.counts.entry(kind).or_insert(0);
+		let index = *count;
+		*count = match count.checked_add(1) {
+			Some(next) => next,
+			None => {
+				return Err(CompileError::new(
+					ErrorKind::TooManyVariables,
+					format!(
+						"`{}` is one {} variable too many, at most {} can be declared",
+						name,
+						kind,
+						u16::MAX
+					),
+					span,
+				))
+			}
+		};
+
+		let symbol = Symbol {
+			name: name.to_string(),
+			typing,
+			kind,
+			index,
+			span,
+		};
+
+		Ok(self.symbols.insert(name.to_string(), symbol))
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Symbol> {
+		self.symbols.get(name)
+	}
+
+	/// The number of variables of the kind, which is also the size of their
+	/// segment
+	pub fn count(&self, kind: SymbolKind) -> u16 {
+		self.counts.get(&kind).copied().unwrap_or(0)
+	}
+
+	/// The variables of the scope, in no particular order
+	pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+		self.symbols.values()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::span::Position;
+
+	#[test]
+	fn too_many_variables() {
+		let span = Span::new("Main.jack".into(), Position::start(), Position::start());
+		let mut table = SymbolTable::new();
+
+		for i in 0..u16::MAX {
+			let name = format!("x{}", i);
+			assert!(table
+				.define(&name, Type::Int, SymbolKind::Local, span.clone())
+				.is_ok());
+		}
+
+		let err = table
+			.define("y", Type::Int, SymbolKind::Local, span.clone())
+			.unwrap_err();
+		assert_eq!(err.kind, ErrorKind::TooManyVariables);
+		assert!(table.get("y").is_none());
+		assert_eq!(table.count(SymbolKind::Local), u16::MAX);
+		assert!(table
+			.define("y", Type::Int, SymbolKind::Argument, span)
+			.is_ok());
+	}
+}
@@ -1,131 +1,543 @@
-use regex::{Match, Regex};
-use strum_macros::Display;
+use crate::span::{Position, Span};
+
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString};
+
+#[derive(Display, EnumString, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[strum(serialize_all = "lowercase")]
+pub enum Keyword {
+	Class,
+	Constructor,
+	Function,
+	Method,
+	Field,
+	Static,
+	Var,
+	Int,
+	Char,
+	Boolean,
+	Void,
+	True,
+	False,
+	Null,
+	This,
+	Let,
+	Do,
+	If,
+	Else,
+	While,
+	Return,
+}
+
+#[derive(Display, EnumString, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Symbol {
+	#[strum(serialize = "{")]
+	LeftBrace,
+	#[strum(serialize = "}")]
+	RightBrace,
+	#[strum(serialize = "(")]
+	LeftParen,
+	#[strum(serialize = ")")]
+	RightParen,
+	#[strum(serialize = "[")]
+	LeftBracket,
+	#[strum(serialize = "]")]
+	RightBracket,
+	#[strum(serialize = ".")]
+	Dot,
+	#[strum(serialize = ",")]
+	Comma,
+	#[strum(serialize = ";")]
+	Semicolon,
+	#[strum(serialize = "+")]
+	Plus,
+	#[strum(serialize = "-")]
+	Minus,
+	#[strum(serialize = "*")]
+	Asterisk,
+	#[strum(serialize = "/")]
+	Slash,
+	#[strum(serialize = "&")]
+	Ampersand,
+	#[strum(serialize = "|")]
+	Pipe,
+	#[strum(serialize = "<")]
+	LessThan,
+	#[strum(serialize = ">")]
+	GreaterThan,
+	#[strum(serialize = "=")]
+	Equals,
+	#[strum(serialize = "~")]
+	Tilde,
+}
 
 #[derive(Display, PartialEq, Clone)]
 pub enum TokenType {
-	Keyword,
-	Symbol,
+	Keyword(Keyword),
+	Symbol(Symbol),
 	IntegerConstant,
 	StringConstant,
 	Identifier,
+	Error(LexError),
 	EndOfFile,
 }
 
+/// What went wrong when the tokenizer could not read a token
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexError {
+	UnknownCharacter(char),
+	UnterminatedComment,
+	UnterminatedString,
+	NewlineInString,
+	IntegerOutOfRange(String),
+}
+
+impl fmt::Display for LexError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			LexError::UnknownCharacter(c) => write!(f, "unknown character {:?}", c),
+			LexError::UnterminatedComment => write!(f, "unterminated comment, expected `*/`"),
+			LexError::UnterminatedString => {
+				write!(f, "unterminated string constant, expected `\"`")
+			}
+			LexError::NewlineInString => {
+				write!(
+					f,
+					"newline in string constant, expected `\"` before the end of the line"
+				)
+			}
+			LexError::IntegerOutOfRange(digits) => write!(
+				f,
+				"integer constant {} is out of range, the maximum is {}",
+				digits, MAX_INTEGER
+			),
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TriviaKind {
+	Whitespace,
+	LineComment,
+	BlockComment,
+}
+
+/// Source text between tokens that does not affect the program
+#[derive(Debug, Clone)]
+pub struct Trivia<'a> {
+	pub kind: TriviaKind,
+	pub text: &'a str,
+}
+
+impl<'a> Trivia<'a> {
+	/// Returns the text between `/**` and `*/` if the trivia is a doc comment
+	pub fn doc(&self) -> Option<&'a str> {
+		if self.kind != TriviaKind::BlockComment || self.text == "/**/" {
+			return None;
+		}
+
+		let text = self.text;
+		text.strip_prefix("/**").map(|doc| &doc[..doc.len() - 2])
+	}
+}
+
+/// A token borrowing its value from the tokenized source. The value of a
+/// string constant does not include the surrounding quotes.
+///
+/// When tokenizing with trivia, a token owns the trivia that follows it up to
+/// the end of its line, and the token after it owns the rest. The trivia at
+/// the end of the file is owned by the `EndOfFile` token.
+///
+/// Doc comments (`/** ... */`) are kept with or without trivia: `doc` holds
+/// the text of the last one before the token.
 #[derive(Clone)]
-pub struct Token {
+pub struct Token<'a> {
 	pub token: TokenType,
-	pub value: String,
+	pub value: &'a str,
+	pub span: Span,
+	pub doc: Option<&'a str>,
+	pub leading_trivia: Vec<Trivia<'a>>,
+	pub trailing_trivia: Vec<Trivia<'a>>,
+}
+
+impl<'a> Token<'a> {
+	pub fn new(token: TokenType, value: &'a str, span: Span) -> Token<'a> {
+		Token {
+			token,
+			value,
+			span,
+			doc: None,
+			leading_trivia: Vec::new(),
+			trailing_trivia: Vec::new(),
+		}
+	}
+
+	pub fn is_keyword(&self, keyword: Keyword) -> bool {
+		self.token == TokenType::Keyword(keyword)
+	}
+
+	pub fn is_symbol(&self, symbol: Symbol) -> bool {
+		self.token == TokenType::Symbol(symbol)
+	}
+
+	/// Appends the token exactly as it appears in the source, trivia included
+	pub fn write_source(&self, out: &mut String) {
+		for trivia in &self.leading_trivia {
+			out.push_str(trivia.text);
+		}
+
+		if self.token == TokenType::StringConstant {
+			out.push('"');
+			out.push_str(self.value);
+			out.push('"');
+		} else {
+			out.push_str(self.value);
+		}
+
+		for trivia in &self.trailing_trivia {
+			out.push_str(trivia.text);
+		}
+	}
 }
 
-pub struct Tokenizer {
-	code: String,
+impl<'a> fmt::Display for Token<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.token {
+			TokenType::EndOfFile => write!(f, "end of file"),
+			TokenType::StringConstant => write!(f, "`\"{}\"`", self.value),
+			_ => write!(f, "`{}`", self.value),
+		}
+	}
 }
 
-lazy_static! {
-	static ref KEYWORDS: Regex = Regex::new(r#"^(class|constructor|function|method|field|static|var|int|char|boolean|void|true|false|null|this|let|do|if|else|while|return)\b"#).unwrap();
-	static ref SYMBOLS: Regex = Regex::new(r#"^[\{\}\(\)\[\].,;\+\-\*/&|<>=~]"#).unwrap();
-	static ref IDENTIFIERS: Regex = Regex::new("^[_[:alpha:]][_[:alnum:]]*").unwrap();
-	static ref INTEGER_CONSTANTS: Regex = Regex::new("^[0-9]{1,5}").unwrap();
-	static ref STRING_CONSTANTS: Regex = Regex::new(r#"^".*""#).unwrap();
-	static ref INLINE_COMMENT: Regex = Regex::new("^//.*").unwrap();
-	static ref MULTILINE_COMMENT: Regex = Regex::new(r#"^(/\*)(.|\n)*?(\*/)"#).unwrap();
+/// Splits Jack source code into tokens by moving a cursor over the code,
+/// without copying it. As an iterator, it yields every token of the code
+/// followed by a single `EndOfFile` token.
+pub struct Tokenizer<'a> {
+	code: &'a str,
+	file: Rc<str>,
+	position: Position,
+	done: bool,
+	// Whether tokens keep the trivia around them
+	trivia: bool,
 }
 
-impl Tokenizer {
-	fn remove_n_first_chars(&mut self, count: usize) {
-		for _ in 0..count {
-			self.code.remove(0);
+const MAX_INTEGER: u32 = 32767;
+
+impl<'a> Tokenizer<'a> {
+	// The code that has not been tokenized yet
+	fn rest(&self) -> &'a str {
+		&self.code[self.position.offset..]
+	}
+
+	// Moves the cursor `count` bytes forward, keeping track of lines and columns
+	fn advance(&mut self, count: usize) {
+		let code = self.code;
+		let offset = self.position.offset;
+		self.position.advance(&code[offset..offset + count]);
+	}
+
+	// Consumes `count` bytes and returns them as a token of the given type
+	fn token(&mut self, token: TokenType, count: usize) -> Token<'a> {
+		let start = self.position;
+		let value = &self.rest()[..count];
+		self.advance(count);
+
+		Token::new(token, value, Span::new(self.file.clone(), start, self.position))
+	}
+
+	// Consumes `count` bytes as trivia of the given kind
+	fn trivia(&mut self, kind: TriviaKind, count: usize) -> Trivia<'a> {
+		let text = &self.rest()[..count];
+		self.advance(count);
+		Trivia { kind, text }
+	}
+
+	// Reads the whitespace or comment at the cursor, if any. With `line_end`
+	// set, whitespace is only read up to the end of the line.
+	fn read_trivia(&mut self, line_end: bool) -> Option<Trivia<'a>> {
+		let rest = self.rest();
+		let mut whitespace = rest.len() - rest.trim_start().len();
+
+		if whitespace != 0 {
+			if line_end {
+				if let Some(newline) = rest[..whitespace].find('\n') {
+					whitespace = newline + 1;
+				}
+			}
+			return Some(self.trivia(TriviaKind::Whitespace, whitespace));
 		}
+
+		if rest.starts_with("//") {
+			let end = rest.find('\n').unwrap_or(rest.len());
+			return Some(self.trivia(TriviaKind::LineComment, end));
+		}
+
+		if let Some(comment) = rest.strip_prefix("/*") {
+			if let Some(end) = comment.find("*/") {
+				return Some(self.trivia(TriviaKind::BlockComment, end + 4));
+			}
+		}
+
+		None
 	}
 
-	pub fn new(code: String) -> Tokenizer {
-		Tokenizer { code: code }
+	// Returns whether the code starts with a comment that is never closed
+	fn at_unterminated_comment(&self) -> bool {
+		self.rest().starts_with("/*")
 	}
 
-	// Returns the next token in the code
-	pub fn next(&mut self) -> Token {
-		self.code = self.code.trim_start().to_owned();
-		let code = self.code.clone();
+	pub fn new(code: &'a str, file: &str) -> Tokenizer<'a> {
+		Tokenizer {
+			code,
+			file: Rc::from(file),
+			position: Position::start(),
+			done: false,
+			trivia: false,
+		}
+	}
+
+	/// Creates a tokenizer whose tokens keep their surrounding whitespace and
+	/// comments, so that the source can be rebuilt from them.
+	pub fn with_trivia(code: &'a str, file: &str) -> Tokenizer<'a> {
+		Tokenizer {
+			trivia: true,
+			..Tokenizer::new(code, file)
+		}
+	}
 
-		if INLINE_COMMENT.is_match(&code) {
-			let bounds: Match = INLINE_COMMENT.find(&code).unwrap();
+	// Returns the next token in the code, with its trivia when they are kept
+	fn next_token(&mut self) -> Token<'a> {
+		let mut leading_trivia = Vec::new();
+		let mut doc = None;
+		while let Some(trivia) = self.read_trivia(false) {
+			if let Some(text) = trivia.doc() {
+				doc = Some(text);
+			}
+			if self.trivia {
+				leading_trivia.push(trivia);
+			}
+		}
+
+		let mut token = self.read_token();
+		token.doc = doc;
+		if !self.trivia {
+			return token;
+		}
 
-			self.remove_n_first_chars(bounds.end() - bounds.start());
+		token.leading_trivia = leading_trivia;
+		if token.token != TokenType::EndOfFile {
+			let ends_line = |trivia: &Trivia| trivia.text.ends_with('\n');
+			while !token.trailing_trivia.last().is_some_and(ends_line) {
+				match self.read_trivia(true) {
+					Some(trivia) => token.trailing_trivia.push(trivia),
+					None => break,
+				}
+			}
+		}
+		token
+	}
 
-			return self.next();
+	// Reads the token at the cursor, which is not on trivia
+	fn read_token(&mut self) -> Token<'a> {
+		let rest = self.rest();
+		let first = match rest.chars().next() {
+			Some(c) => c,
+			None => return self.token(TokenType::EndOfFile, 0),
 		};
 
-		if MULTILINE_COMMENT.is_match(&code) {
-			let bounds: Match = MULTILINE_COMMENT.find(&code).unwrap();
+		if self.at_unterminated_comment() {
+			return self.token(TokenType::Error(LexError::UnterminatedComment), rest.len());
+		}
 
-			self.remove_n_first_chars(bounds.end() - bounds.start());
+		if let Ok(symbol) = Symbol::from_str(&rest[..first.len_utf8()]) {
+			return self.token(TokenType::Symbol(symbol), 1);
+		}
 
-			return self.next();
-		};
+		if first.is_ascii_digit() {
+			let length = rest.bytes().take_while(|c| c.is_ascii_digit()).count();
+			let digits = &rest[..length];
+
+			// Leading zeros aside, anything longer than 5 digits is out of range
+			let significant = digits.trim_start_matches('0');
+			if significant.len() > 5 || significant.parse::<u32>().unwrap_or(0) > MAX_INTEGER {
+				let err = LexError::IntegerOutOfRange(digits.to_string());
+				return self.token(TokenType::Error(err), length);
+			}
 
-		if KEYWORDS.is_match(&code) {
-			let bounds: Match = KEYWORDS.find(&code).unwrap();
-			let value: String = code[bounds.start()..bounds.end()].to_owned();
+			return self.token(TokenType::IntegerConstant, length);
+		}
 
-			self.remove_n_first_chars(value.len());
+		if first == '"' {
+			// The constant runs until the next quote, and may not span several lines
+			let end = rest[1..].find(&['"', '\n', '\r'][..]);
 
-			return Token {
-				token: TokenType::Keyword,
-				value: value,
+			return match end.map(|end| (end + 1, rest.as_bytes()[end + 1])) {
+				Some((end, b'"')) => {
+					let start = self.position;
+					self.advance(end + 1);
+
+					let span = Span::new(self.file.clone(), start, self.position);
+					Token::new(TokenType::StringConstant, &rest[1..end], span)
+				}
+				Some((end, _)) => self.token(TokenType::Error(LexError::NewlineInString), end),
+				None => self.token(TokenType::Error(LexError::UnterminatedString), rest.len()),
 			};
-		};
+		}
 
-		if SYMBOLS.is_match(&code) {
-			let bounds: Match = SYMBOLS.find(&code).unwrap();
-			let value: String = code[bounds.start()..bounds.end()].to_owned();
+		if first.is_alphabetic() || first == '_' {
+			// Non-ASCII letters are read as part of the word, so that the whole
+			// word is reported rather than split around them
+			let length = rest
+				.find(|c: char| !c.is_alphanumeric() && c != '_')
+				.unwrap_or(rest.len());
+			let word = &rest[..length];
 
-			self.remove_n_first_chars(value.len());
+			if let Some(c) = word.chars().find(|c| !c.is_ascii()) {
+				return self.token(TokenType::Error(LexError::UnknownCharacter(c)), length);
+			}
 
-			return Token {
-				token: TokenType::Symbol,
-				value: value,
-			};
-		};
+			if let Ok(keyword) = Keyword::from_str(word) {
+				return self.token(TokenType::Keyword(keyword), length);
+			}
 
-		if INTEGER_CONSTANTS.is_match(&code) {
-			let bounds: Match = INTEGER_CONSTANTS.find(&code).unwrap();
-			let value: String = code[bounds.start()..bounds.end()].to_owned();
+			return self.token(TokenType::Identifier, length);
+		}
 
-			self.remove_n_first_chars(value.len());
+		self.token(
+			TokenType::Error(LexError::UnknownCharacter(first)),
+			first.len_utf8(),
+		)
+	}
+}
 
-			return Token {
-				token: TokenType::IntegerConstant,
-				value: value,
-			};
-		};
+impl<'a> Iterator for Tokenizer<'a> {
+	type Item = Token<'a>;
 
-		if STRING_CONSTANTS.is_match(&code) {
-			let bounds: Match = STRING_CONSTANTS.find(&code).unwrap();
-			let value: String = code[(bounds.start() + 1)..(bounds.end() - 1)].to_owned();
+	fn next(&mut self) -> Option<Token<'a>> {
+		if self.done {
+			return None;
+		}
 
-			self.remove_n_first_chars(value.len() + 2);
+		let token = self.next_token();
+		self.done = token.token == TokenType::EndOfFile;
+		Some(token)
+	}
+}
 
-			return Token {
-				token: TokenType::StringConstant,
-				value: value,
-			};
-		};
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-		if IDENTIFIERS.is_match(&code) {
-			let bounds: Match = IDENTIFIERS.find(&code).unwrap();
-			let value: String = code[bounds.start()..bounds.end()].to_owned();
+	fn tokens(code: &str) -> Vec<Token<'_>> {
+		Tokenizer::new(code, "Main.jack").collect()
+	}
 
-			self.remove_n_first_chars(value.len());
+	#[test]
+	fn several_strings_on_one_line() {
+		let tokens = tokens("Output.printString(\"a\"); Output.printString(\"b\");");
+		let strings: Vec<&str> = tokens
+			.iter()
+			.filter(|token| token.token == TokenType::StringConstant)
+			.map(|token| token.value)
+			.collect();
 
-			return Token {
-				token: TokenType::Identifier,
-				value: value,
-			};
-		};
+		assert_eq!(strings, vec!["a", "b"]);
+	}
 
-		return Token {
-			token: TokenType::EndOfFile,
-			value: String::new(),
-		};
+	#[test]
+	fn newline_in_string() {
+		let tokens = tokens("let s = \"abc\nx\";");
+		let token = &tokens[3];
+
+		assert!(token.token == TokenType::Error(LexError::NewlineInString));
+		assert_eq!(token.value, "\"abc");
+		assert_eq!(
+			token.span.start,
+			Position {
+				line: 1,
+				column: 9,
+				offset: 8,
+			}
+		);
+		assert_eq!(
+			token.span.end,
+			Position {
+				line: 1,
+				column: 13,
+				offset: 12,
+			}
+		);
+	}
+
+	#[test]
+	fn unterminated_string() {
+		let tokens = tokens("let s = \"abc");
+		let token = &tokens[3];
+
+		assert!(token.token == TokenType::Error(LexError::UnterminatedString));
+		assert_eq!(token.value, "\"abc");
+		assert!(tokens[4].token == TokenType::EndOfFile);
+	}
+
+	#[test]
+	fn non_ascii_letter_in_word() {
+		let tokens = tokens("classé Main {}");
+		let token = &tokens[0];
+
+		assert!(token.token == TokenType::Error(LexError::UnknownCharacter('é')));
+		assert_eq!(token.value, "classé");
+		assert_eq!(token.span.end.column, 7);
+		assert!(tokens[1].token == TokenType::Identifier);
+		assert_eq!(tokens[1].value, "Main");
+	}
+
+	#[test]
+	fn integer_range() {
+		for code in &["32767", "0032767"] {
+			let tokens = tokens(code);
+
+			assert!(tokens[0].token == TokenType::IntegerConstant);
+			assert_eq!(tokens[0].value, *code);
+			assert!(tokens[1].token == TokenType::EndOfFile);
+		}
+
+		for code in &["32768", "123456"] {
+			let tokens = tokens(code);
+			let err = LexError::IntegerOutOfRange(code.to_string());
+
+			assert!(tokens[0].token == TokenType::Error(err));
+			assert_eq!(tokens[0].value, *code);
+			assert!(tokens[1].token == TokenType::EndOfFile);
+		}
+	}
+
+	#[test]
+	fn source_round_trip() {
+		let code = concat!(
+			"/** Doc */\r\n",
+			"class Main { // line\r\n",
+			"\tfunction void main() {\r\n",
+			"\t\tlet x = 1 # 2; /* block\n*/ let s = \"a\";\n",
+			"\t\treturn;\n",
+			"\t}\n",
+			"}\n",
+			"\n",
+			"// end\n",
+			"  ",
+		);
+		let tokens: Vec<Token> = Tokenizer::with_trivia(code, "Main.jack").collect();
+		let mut source = String::new();
+
+		for token in &tokens {
+			token.write_source(&mut source);
+		}
+
+		assert!(tokens
+			.iter()
+			.any(|token| token.token == TokenType::Error(LexError::UnknownCharacter('#'))));
+		assert_eq!(source, code);
 	}
 }
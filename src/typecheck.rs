@@ -0,0 +1,595 @@
+use crate::ast::*;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::error::{CompileError, ErrorKind};
+use crate::signatures::SignatureTable;
+use crate::span::Span;
+use crate::symbol_table::{Symbol, SymbolTable};
+use crate::visit::Visitor;
+
+use std::fmt;
+use std::rc::Rc;
+
+/// How type errors are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+	/// Types are not checked
+	Off,
+	/// Type errors are warnings, the class is still compiled
+	Warn,
+	/// Type errors stop the compilation of the class
+	Error,
+}
+
+// The type of an expression, as far as it is known
+#[derive(Debug, Clone, PartialEq)]
+enum Inferred {
+	Type(Type),
+	// `null`, which any object variable can hold
+	Null,
+	// Array elements, `void` results and what could not be inferred
+	Unknown,
+}
+
+impl fmt::Display for Inferred {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Inferred::Type(typing) => write!(f, "{}", typing),
+			Inferred::Null => write!(f, "null"),
+			Inferred::Unknown => write!(f, "unknown"),
+		}
+	}
+}
+
+// Characters are numbers in Jack
+fn is_numeric(typing: &Inferred) -> bool {
+	matches!(
+		typing,
+		Inferred::Type(Type::Int) | Inferred::Type(Type::Char) | Inferred::Unknown
+	)
+}
+
+fn is_boolean(typing: &Inferred) -> bool {
+	matches!(typing, Inferred::Type(Type::Boolean) | Inferred::Unknown)
+}
+
+// Whether a variable of the type can hold the value. Jack is loosely typed:
+// characters are numbers, and arrays hold objects of any class.
+fn is_assignable(target: &Type, value: &Inferred) -> bool {
+	match (target, value) {
+		(_, Inferred::Unknown) => true,
+		(Type::Class(_), Inferred::Null) => true,
+		(_, Inferred::Null) => false,
+		(Type::Int, Inferred::Type(Type::Char)) | (Type::Char, Inferred::Type(Type::Int)) => true,
+		(Type::Class(target), Inferred::Type(Type::Class(value))) => {
+			target == value || target == "Array" || value == "Array"
+		}
+		(target, Inferred::Type(value)) => target == value,
+	}
+}
+
+// Whether the values can be compared with `=`
+fn is_comparable(left: &Inferred, right: &Inferred) -> bool {
+	match (left, right) {
+		(Inferred::Type(typing), value) | (value, Inferred::Type(typing)) => {
+			is_assignable(typing, value)
+		}
+		_ => true,
+	}
+}
+
+fn op_symbol(op: BinaryOp) -> &'static str {
+	match op {
+		BinaryOp::Add => "+",
+		BinaryOp::Subtract => "-",
+		BinaryOp::Multiply => "*",
+		BinaryOp::Divide => "/",
+		BinaryOp::And => "&",
+		BinaryOp::Or => "|",
+		BinaryOp::LessThan => "<",
+		BinaryOp::GreaterThan => ">",
+		BinaryOp::Equal => "=",
+	}
+}
+
+/// Infers the types of the expressions of a class and checks that they are
+/// used where their type is expected: conditions, assignments, arguments,
+/// returned values and operands
+pub struct TypeChecker {
+	strictness: Strictness,
+	// The subroutines of the program, `None` if unknown
+	signatures: Option<Rc<SignatureTable>>,
+	class_name: String,
+	class_symbol_table: SymbolTable,
+	func_symbol_table: SymbolTable,
+	// The subroutine being checked
+	subroutine_name: String,
+	subroutine_kind: SubroutineKind,
+	return_type: Option<Type>,
+	errors: Vec<CompileError>,
+}
+
+impl TypeChecker {
+	pub fn new(strictness: Strictness) -> TypeChecker {
+		TypeChecker {
+			strictness,
+			signatures: None,
+			class_name: String::new(),
+			class_symbol_table: SymbolTable::new(),
+			func_symbol_table: SymbolTable::new(),
+			subroutine_name: String::new(),
+			subroutine_kind: SubroutineKind::Function,
+			return_type: None,
+			errors: Vec::new(),
+		}
+	}
+
+	/// Sets the subroutines of every class of the program, so that arguments
+	/// and returned values of calls are checked. By default, calls are of an
+	/// unknown type.
+	pub fn set_signatures(&mut self, signatures: Rc<SignatureTable>) {
+		self.signatures = Some(signatures);
+	}
+
+	/// Returns the type errors of the class, in source order, as warnings or
+	/// errors depending on the strictness
+	pub fn check(&mut self, class: &Class) -> Vec<Diagnostic> {
+		let severity = match self.strictness {
+			Strictness::Off => return Vec::new(),
+			Strictness::Warn => Severity::Warning,
+			Strictness::Error => Severity::Error,
+		};
+
+		self.visit_class(class);
+
+		let mut errors = std::mem::take(&mut self.errors);
+		errors.sort_by_key(|err| err.span.start);
+		errors
+			.into_iter()
+			.map(|err| {
+				let mut diagnostic = Diagnostic::from(err);
+				diagnostic.severity = severity;
+				diagnostic
+			})
+			.collect()
+	}
+
+	fn find_symbol(&self, name: &str) -> Option<&Symbol> {
+		match self.func_symbol_table.get(name) {
+			Some(symbol) => Some(symbol),
+			None => self.class_symbol_table.get(name),
+		}
+	}
+
+	fn mismatch(&mut self, message: String, span: &Span) {
+		self.errors.push(CompileError::new(
+			ErrorKind::TypeMismatch,
+			message,
+			span.clone(),
+		));
+	}
+
+	fn check_condition(&mut self, condition: &Expression) {
+		let typing = self.infer(condition);
+
+		if !is_boolean(&typing) {
+			self.mismatch(
+				format!("expected a `boolean` condition, found `{}`", typing),
+				&condition.span,
+			);
+		}
+	}
+
+	fn check_statements(&mut self, statements: &[Statement]) {
+		for statement in statements {
+			self.visit_statement(statement);
+		}
+	}
+
+	fn infer(&mut self, expression: &Expression) -> Inferred {
+		let mut result = self.infer_term(&expression.term);
+		let mut end = expression.term.span().end;
+
+		for (op, term) in &expression.operations {
+			let left_span = Span::new(expression.span.file.clone(), expression.span.start, end);
+			let right = self.infer_term(term);
+			result = self.infer_operation(*op, (result, &left_span), (right, term.span()));
+			end = term.span().end;
+		}
+
+		result
+	}
+
+	// The type of `left op right`, Jack having no operator precedence
+	fn infer_operation(
+		&mut self,
+		op: BinaryOp,
+		(left, left_span): (Inferred, &Span),
+		(right, right_span): (Inferred, &Span),
+	) -> Inferred {
+		match op {
+			BinaryOp::Add
+			| BinaryOp::Subtract
+			| BinaryOp::Multiply
+			| BinaryOp::Divide
+			| BinaryOp::LessThan
+			| BinaryOp::GreaterThan => {
+				for (typing, span) in [(&left, left_span), (&right, right_span)] {
+					if !is_numeric(typing) {
+						self.mismatch(
+							format!("`{}` expects numbers, found `{}`", op_symbol(op), typing),
+							span,
+						);
+					}
+				}
+
+				if op == BinaryOp::LessThan || op == BinaryOp::GreaterThan {
+					Inferred::Type(Type::Boolean)
+				} else {
+					Inferred::Type(Type::Int)
+				}
+			}
+			// Logical on booleans, bitwise on numbers
+			BinaryOp::And | BinaryOp::Or => {
+				if is_boolean(&left) && is_boolean(&right) {
+					if left == Inferred::Unknown {
+						right
+					} else {
+						left
+					}
+				} else if is_numeric(&left) && is_numeric(&right) {
+					Inferred::Type(Type::Int)
+				} else {
+					self.mismatch(
+						format!(
+							"`{}` expects two booleans or two numbers, found `{}` and `{}`",
+							op_symbol(op),
+							left,
+							right
+						),
+						right_span,
+					);
+					Inferred::Unknown
+				}
+			}
+			BinaryOp::Equal => {
+				let both_numeric = is_numeric(&left) && is_numeric(&right);
+
+				if !both_numeric && !is_comparable(&left, &right) {
+					self.mismatch(
+						format!("cannot compare `{}` with `{}`", left, right),
+						right_span,
+					);
+				}
+				Inferred::Type(Type::Boolean)
+			}
+		}
+	}
+
+	fn infer_term(&mut self, term: &Term) -> Inferred {
+		match term {
+			Term::IntegerConstant { .. } => Inferred::Type(Type::Int),
+			Term::StringConstant { .. } => Inferred::Type(Type::Class("String".to_string())),
+			Term::KeywordConstant { value, .. } => match value {
+				KeywordConstant::True | KeywordConstant::False => Inferred::Type(Type::Boolean),
+				KeywordConstant::Null => Inferred::Null,
+				// Functions have no current object
+				KeywordConstant::This if self.subroutine_kind == SubroutineKind::Function => {
+					Inferred::Unknown
+				}
+				KeywordConstant::This => Inferred::Type(Type::Class(self.class_name.clone())),
+			},
+			Term::Variable(name) => match self.find_symbol(&name.name) {
+				Some(symbol) => Inferred::Type(symbol.typing.clone()),
+				None => Inferred::Unknown,
+			},
+			Term::ArrayAccess { name, index, .. } => {
+				self.check_array(name);
+				self.check_index(index);
+				Inferred::Unknown
+			}
+			Term::SubroutineCall(call) => self.infer_call(call),
+			Term::Parenthesized { expression, .. } => self.infer(expression),
+			Term::Unary { op, term, span } => {
+				let typing = self.infer_term(term);
+
+				match op {
+					UnaryOp::Negate if is_numeric(&typing) => Inferred::Type(Type::Int),
+					UnaryOp::Negate => {
+						self.mismatch(format!("`-` expects a number, found `{}`", typing), span);
+						Inferred::Unknown
+					}
+					UnaryOp::Not if is_boolean(&typing) => typing,
+					UnaryOp::Not if is_numeric(&typing) => Inferred::Type(Type::Int),
+					UnaryOp::Not => {
+						self.mismatch(
+							format!("`~` expects a boolean or a number, found `{}`", typing),
+							span,
+						);
+						Inferred::Unknown
+					}
+				}
+			}
+		}
+	}
+
+	// Whether the class is part of the program or of the OS, classes being
+	// assumed to exist when the program is unknown
+	fn is_known_class(&self, name: &str) -> bool {
+		match &self.signatures {
+			Some(signatures) => signatures.has_class(name),
+			None => true,
+		}
+	}
+
+	// Checks that the type of a declaration exists
+	fn check_type(&mut self, typing: &Type, span: &Span) {
+		if let Type::Class(name) = typing {
+			if !self.is_known_class(name) {
+				self.errors.push(CompileError::new(
+					ErrorKind::UnknownClass,
+					format!("unknown type `{}`", name),
+					span.clone(),
+				));
+			}
+		}
+	}
+
+	// Checks that the variable is an array
+	fn check_array(&mut self, name: &Identifier) {
+		let typing = match self.find_symbol(&name.name) {
+			Some(symbol) => symbol.typing.clone(),
+			None => return,
+		};
+
+		if typing != Type::Class("Array".to_string()) {
+			self.mismatch(
+				format!("`{}` of type `{}` cannot be indexed", name.name, typing),
+				&name.span,
+			);
+		}
+	}
+
+	fn check_index(&mut self, index: &Expression) {
+		let typing = self.infer(index);
+
+		if !is_numeric(&typing) {
+			self.mismatch(
+				format!("array indices are numbers, found `{}`", typing),
+				&index.span,
+			);
+		}
+	}
+
+	// The type returned by the call, checking its arguments
+	fn infer_call(&mut self, call: &SubroutineCall) -> Inferred {
+		let arguments: Vec<Inferred> = call
+			.arguments
+			.iter()
+			.map(|argument| self.infer(argument))
+			.collect();
+
+		// Calls on unknown classes and on values of primitive types are
+		// reported by the code generator
+		let class_name = match &call.receiver {
+			Some(receiver) => match self.find_symbol(&receiver.name) {
+				Some(Symbol {
+					typing: Type::Class(class_name),
+					..
+				}) => class_name.clone(),
+				Some(_) => return Inferred::Unknown,
+				None => receiver.name.clone(),
+			},
+			None => self.class_name.clone(),
+		};
+
+		let signature = match &self.signatures {
+			Some(signatures) => match signatures.get(&class_name, &call.name.name) {
+				Some(signature) => signature.clone(),
+				None => return Inferred::Unknown,
+			},
+			None => return Inferred::Unknown,
+		};
+
+		// Missing and extra arguments are reported by the code generator
+		for ((argument, typing), parameter) in call
+			.arguments
+			.iter()
+			.zip(&arguments)
+			.zip(&signature.parameters)
+		{
+			if !is_assignable(parameter, typing) {
+				let err = CompileError::new(
+					ErrorKind::TypeMismatch,
+					format!(
+						"`{}` expects `{}` here, found `{}`",
+						signature.full_name(),
+						parameter,
+						typing
+					),
+					argument.span.clone(),
+				);
+				self.errors.push(match &signature.span {
+					Some(span) => err.with_label(span.clone(), "declared here"),
+					None => err,
+				});
+			}
+		}
+
+		match signature.return_type {
+			Some(typing) => Inferred::Type(typing),
+			None => Inferred::Unknown,
+		}
+	}
+}
+
+impl Visitor for TypeChecker {
+	fn visit_class(&mut self, class: &Class) {
+		self.class_name = class.name.name.clone();
+		// Duplicate declarations are reported by the code generator
+		self.class_symbol_table = SymbolTable::for_class(class, &mut Vec::new());
+
+		for var_dec in &class.vars {
+			self.check_type(&var_dec.typing, &var_dec.type_span);
+		}
+
+		for subroutine in &class.subroutines {
+			self.visit_subroutine_dec(subroutine);
+		}
+	}
+
+	fn visit_subroutine_dec(&mut self, subroutine: &SubroutineDec) {
+		self.func_symbol_table =
+			SymbolTable::for_subroutine(&self.class_name, subroutine, &mut Vec::new());
+		self.subroutine_name = subroutine.name.name.clone();
+		self.subroutine_kind = subroutine.kind;
+		self.return_type = subroutine.return_type.clone();
+
+		if let Some(return_type) = &subroutine.return_type {
+			self.check_type(return_type, &subroutine.return_type_span);
+		}
+		for parameter in &subroutine.parameters {
+			self.check_type(&parameter.typing, &parameter.type_span);
+		}
+		for var_dec in &subroutine.body.vars {
+			self.check_type(&var_dec.typing, &var_dec.type_span);
+		}
+
+		self.check_statements(&subroutine.body.statements);
+	}
+
+	fn visit_let_statement(&mut self, statement: &LetStatement) {
+		let value = self.infer(&statement.value);
+
+		if let Some(index) = &statement.index {
+			self.check_array(&statement.name);
+			self.check_index(index);
+			return;
+		}
+
+		let symbol = match self.find_symbol(&statement.name.name) {
+			Some(symbol) => symbol.clone(),
+			None => return,
+		};
+
+		if !is_assignable(&symbol.typing, &value) {
+			let err = CompileError::new(
+				ErrorKind::TypeMismatch,
+				format!(
+					"cannot assign `{}` to `{}` of type `{}`",
+					value, symbol.name, symbol.typing
+				),
+				statement.value.span.clone(),
+			);
+			self.errors
+				.push(err.with_label(symbol.span, "declared here"));
+		}
+	}
+
+	fn visit_if_statement(&mut self, statement: &IfStatement) {
+		self.check_condition(&statement.condition);
+		self.check_statements(&statement.statements);
+
+		if let Some(else_statements) = &statement.else_statements {
+			self.check_statements(else_statements);
+		}
+	}
+
+	fn visit_while_statement(&mut self, statement: &WhileStatement) {
+		self.check_condition(&statement.condition);
+		self.check_statements(&statement.statements);
+	}
+
+	fn visit_do_statement(&mut self, statement: &DoStatement) {
+		self.infer_call(&statement.call);
+	}
+
+	fn visit_return_statement(&mut self, statement: &ReturnStatement) {
+		let return_type = self.return_type.clone();
+
+		match (&statement.value, return_type) {
+			(Some(value), Some(return_type)) => {
+				let typing = self.infer(value);
+
+				if !is_assignable(&return_type, &typing) {
+					let message = format!(
+						"`{}` returns `{}`, found `{}`",
+						self.subroutine_name, return_type, typing
+					);
+					self.mismatch(message, &value.span);
+				}
+			}
+			(Some(value), None) => {
+				self.infer(value);
+				let message = format!("`{}` is `void` but returns a value", self.subroutine_name);
+				self.mismatch(message, &value.span);
+			}
+			(None, Some(return_type)) => {
+				let message = format!(
+					"`{}` returns `{}`, found no value",
+					self.subroutine_name, return_type
+				);
+				self.mismatch(message, &statement.span);
+			}
+			(None, None) => {}
+		}
+	}
+
+	fn visit_expression(&mut self, expression: &Expression) {
+		self.infer(expression);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::parser::Parser;
+	use crate::tokenizer::Tokenizer;
+
+	fn check(code: &str) -> Vec<String> {
+		let class = Parser::new(Tokenizer::new(code, "Main.jack"))
+			.parse()
+			.unwrap();
+		let mut signatures = SignatureTable::with_os();
+		signatures.add_class(&class);
+
+		let mut checker = TypeChecker::new(Strictness::Error);
+		checker.set_signatures(Rc::new(signatures));
+		checker
+			.check(&class)
+			.into_iter()
+			.map(|diagnostic| diagnostic.message)
+			.collect()
+	}
+
+	#[test]
+	fn call_on_object_of_unknown_class() {
+		let messages =
+			check("class Main { function void main() { var Foo f; do f.bar(); return; } }");
+
+		assert_eq!(messages, vec!["unknown type `Foo`"]);
+	}
+
+	#[test]
+	fn declarations_of_known_classes() {
+		let messages = check(
+			"class Main { field Array a; function String main(Main m) { var String s; return s; } }",
+		);
+
+		assert!(messages.is_empty());
+	}
+
+	#[test]
+	fn unknown_type_points_at_its_name() {
+		let code = "class Main { function Foo main() { return null; } }";
+		let class = Parser::new(Tokenizer::new(code, "Main.jack"))
+			.parse()
+			.unwrap();
+		let mut checker = TypeChecker::new(Strictness::Error);
+		checker.set_signatures(Rc::new(SignatureTable::with_os()));
+		let diagnostics = checker.check(&class);
+
+		assert_eq!(diagnostics.len(), 1);
+		assert_eq!(diagnostics[0].code, Some(ErrorKind::UnknownClass.code()));
+		assert_eq!(&code[diagnostics[0].span.range()], "Foo");
+	}
+}
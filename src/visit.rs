@@ -0,0 +1,414 @@
+use crate::ast::*;
+
+/// Walks a syntax tree by reference. Every `visit_` method walks the children
+/// of its node by default, so an implementation only overrides the nodes it is
+/// interested in, calling the matching `walk_` function to keep walking below.
+pub trait Visitor {
+	fn visit_class(&mut self, class: &Class) {
+		walk_class(self, class)
+	}
+
+	fn visit_class_var_dec(&mut self, var_dec: &ClassVarDec) {
+		walk_class_var_dec(self, var_dec)
+	}
+
+	fn visit_subroutine_dec(&mut self, subroutine: &SubroutineDec) {
+		walk_subroutine_dec(self, subroutine)
+	}
+
+	fn visit_parameter(&mut self, parameter: &Parameter) {
+		walk_parameter(self, parameter)
+	}
+
+	fn visit_subroutine_body(&mut self, body: &SubroutineBody) {
+		walk_subroutine_body(self, body)
+	}
+
+	fn visit_var_dec(&mut self, var_dec: &VarDec) {
+		walk_var_dec(self, var_dec)
+	}
+
+	fn visit_statement(&mut self, statement: &Statement) {
+		walk_statement(self, statement)
+	}
+
+	fn visit_let_statement(&mut self, statement: &LetStatement) {
+		walk_let_statement(self, statement)
+	}
+
+	fn visit_if_statement(&mut self, statement: &IfStatement) {
+		walk_if_statement(self, statement)
+	}
+
+	fn visit_while_statement(&mut self, statement: &WhileStatement) {
+		walk_while_statement(self, statement)
+	}
+
+	fn visit_do_statement(&mut self, statement: &DoStatement) {
+		walk_do_statement(self, statement)
+	}
+
+	fn visit_return_statement(&mut self, statement: &ReturnStatement) {
+		walk_return_statement(self, statement)
+	}
+
+	fn visit_expression(&mut self, expression: &Expression) {
+		walk_expression(self, expression)
+	}
+
+	fn visit_term(&mut self, term: &Term) {
+		walk_term(self, term)
+	}
+
+	fn visit_subroutine_call(&mut self, call: &SubroutineCall) {
+		walk_subroutine_call(self, call)
+	}
+
+	fn visit_identifier(&mut self, _identifier: &Identifier) {}
+
+	fn visit_type(&mut self, _typing: &Type) {}
+}
+
+pub fn walk_class<V: Visitor + ?Sized>(visitor: &mut V, class: &Class) {
+	visitor.visit_identifier(&class.name);
+	for var_dec in &class.vars {
+		visitor.visit_class_var_dec(var_dec);
+	}
+	for subroutine in &class.subroutines {
+		visitor.visit_subroutine_dec(subroutine);
+	}
+}
+
+pub fn walk_class_var_dec<V: Visitor + ?Sized>(visitor: &mut V, var_dec: &ClassVarDec) {
+	visitor.visit_type(&var_dec.typing);
+	for name in &var_dec.names {
+		visitor.visit_identifier(name);
+	}
+}
+
+pub fn walk_subroutine_dec<V: Visitor + ?Sized>(visitor: &mut V, subroutine: &SubroutineDec) {
+	if let Some(return_type) = &subroutine.return_type {
+		visitor.visit_type(return_type);
+	}
+	visitor.visit_identifier(&subroutine.name);
+	for parameter in &subroutine.parameters {
+		visitor.visit_parameter(parameter);
+	}
+	visitor.visit_subroutine_body(&subroutine.body);
+}
+
+pub fn walk_parameter<V: Visitor + ?Sized>(visitor: &mut V, parameter: &Parameter) {
+	visitor.visit_type(&parameter.typing);
+	visitor.visit_identifier(&parameter.name);
+}
+
+pub fn walk_subroutine_body<V: Visitor + ?Sized>(visitor: &mut V, body: &SubroutineBody) {
+	for var_dec in &body.vars {
+		visitor.visit_var_dec(var_dec);
+	}
+	for statement in &body.statements {
+		visitor.visit_statement(statement);
+	}
+}
+
+pub fn walk_var_dec<V: Visitor + ?Sized>(visitor: &mut V, var_dec: &VarDec) {
+	visitor.visit_type(&var_dec.typing);
+	for name in &var_dec.names {
+		visitor.visit_identifier(name);
+	}
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+	match statement {
+		Statement::Let(statement) => visitor.visit_let_statement(statement),
+		Statement::If(statement) => visitor.visit_if_statement(statement),
+		Statement::While(statement) => visitor.visit_while_statement(statement),
+		Statement::Do(statement) => visitor.visit_do_statement(statement),
+		Statement::Return(statement) => visitor.visit_return_statement(statement),
+	}
+}
+
+pub fn walk_let_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &LetStatement) {
+	visitor.visit_identifier(&statement.name);
+	if let Some(index) = &statement.index {
+		visitor.visit_expression(index);
+	}
+	visitor.visit_expression(&statement.value);
+}
+
+pub fn walk_if_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &IfStatement) {
+	visitor.visit_expression(&statement.condition);
+	for statement in &statement.statements {
+		visitor.visit_statement(statement);
+	}
+	if let Some(else_statements) = &statement.else_statements {
+		for statement in else_statements {
+			visitor.visit_statement(statement);
+		}
+	}
+}
+
+pub fn walk_while_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &WhileStatement) {
+	visitor.visit_expression(&statement.condition);
+	for statement in &statement.statements {
+		visitor.visit_statement(statement);
+	}
+}
+
+pub fn walk_do_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &DoStatement) {
+	visitor.visit_subroutine_call(&statement.call);
+}
+
+pub fn walk_return_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &ReturnStatement) {
+	if let Some(value) = &statement.value {
+		visitor.visit_expression(value);
+	}
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+	visitor.visit_term(&expression.term);
+	for (_, term) in &expression.operations {
+		visitor.visit_term(term);
+	}
+}
+
+pub fn walk_term<V: Visitor + ?Sized>(visitor: &mut V, term: &Term) {
+	match term {
+		Term::IntegerConstant { .. }
+		| Term::StringConstant { .. }
+		| Term::KeywordConstant { .. } => {}
+		Term::Variable(name) => visitor.visit_identifier(name),
+		Term::ArrayAccess { name, index, .. } => {
+			visitor.visit_identifier(name);
+			visitor.visit_expression(index);
+		}
+		Term::SubroutineCall(call) => visitor.visit_subroutine_call(call),
+		Term::Parenthesized { expression, .. } => visitor.visit_expression(expression),
+		Term::Unary { term, .. } => visitor.visit_term(term),
+	}
+}
+
+pub fn walk_subroutine_call<V: Visitor + ?Sized>(visitor: &mut V, call: &SubroutineCall) {
+	if let Some(receiver) = &call.receiver {
+		visitor.visit_identifier(receiver);
+	}
+	visitor.visit_identifier(&call.name);
+	for argument in &call.arguments {
+		visitor.visit_expression(argument);
+	}
+}
+
+/// Walks a syntax tree by mutable reference to rewrite it in place, the same
+/// way `Visitor` does
+pub trait VisitorMut {
+	fn visit_class(&mut self, class: &mut Class) {
+		walk_class_mut(self, class)
+	}
+
+	fn visit_class_var_dec(&mut self, var_dec: &mut ClassVarDec) {
+		walk_class_var_dec_mut(self, var_dec)
+	}
+
+	fn visit_subroutine_dec(&mut self, subroutine: &mut SubroutineDec) {
+		walk_subroutine_dec_mut(self, subroutine)
+	}
+
+	fn visit_parameter(&mut self, parameter: &mut Parameter) {
+		walk_parameter_mut(self, parameter)
+	}
+
+	fn visit_subroutine_body(&mut self, body: &mut SubroutineBody) {
+		walk_subroutine_body_mut(self, body)
+	}
+
+	fn visit_var_dec(&mut self, var_dec: &mut VarDec) {
+		walk_var_dec_mut(self, var_dec)
+	}
+
+	fn visit_statement(&mut self, statement: &mut Statement) {
+		walk_statement_mut(self, statement)
+	}
+
+	fn visit_let_statement(&mut self, statement: &mut LetStatement) {
+		walk_let_statement_mut(self, statement)
+	}
+
+	fn visit_if_statement(&mut self, statement: &mut IfStatement) {
+		walk_if_statement_mut(self, statement)
+	}
+
+	fn visit_while_statement(&mut self, statement: &mut WhileStatement) {
+		walk_while_statement_mut(self, statement)
+	}
+
+	fn visit_do_statement(&mut self, statement: &mut DoStatement) {
+		walk_do_statement_mut(self, statement)
+	}
+
+	fn visit_return_statement(&mut self, statement: &mut ReturnStatement) {
+		walk_return_statement_mut(self, statement)
+	}
+
+	fn visit_expression(&mut self, expression: &mut Expression) {
+		walk_expression_mut(self, expression)
+	}
+
+	fn visit_term(&mut self, term: &mut Term) {
+		walk_term_mut(self, term)
+	}
+
+	fn visit_subroutine_call(&mut self, call: &mut SubroutineCall) {
+		walk_subroutine_call_mut(self, call)
+	}
+
+	fn visit_identifier(&mut self, _identifier: &mut Identifier) {}
+
+	fn visit_type(&mut self, _typing: &mut Type) {}
+}
+
+pub fn walk_class_mut<V: VisitorMut + ?Sized>(visitor: &mut V, class: &mut Class) {
+	visitor.visit_identifier(&mut class.name);
+	for var_dec in &mut class.vars {
+		visitor.visit_class_var_dec(var_dec);
+	}
+	for subroutine in &mut class.subroutines {
+		visitor.visit_subroutine_dec(subroutine);
+	}
+}
+
+pub fn walk_class_var_dec_mut<V: VisitorMut + ?Sized>(visitor: &mut V, var_dec: &mut ClassVarDec) {
+	visitor.visit_type(&mut var_dec.typing);
+	for name in &mut var_dec.names {
+		visitor.visit_identifier(name);
+	}
+}
+
+pub fn walk_subroutine_dec_mut<V: VisitorMut + ?Sized>(
+	visitor: &mut V,
+	subroutine: &mut SubroutineDec,
+) {
+	if let Some(return_type) = &mut subroutine.return_type {
+		visitor.visit_type(return_type);
+	}
+	visitor.visit_identifier(&mut subroutine.name);
+	for parameter in &mut subroutine.parameters {
+		visitor.visit_parameter(parameter);
+	}
+	visitor.visit_subroutine_body(&mut subroutine.body);
+}
+
+pub fn walk_parameter_mut<V: VisitorMut + ?Sized>(visitor: &mut V, parameter: &mut Parameter) {
+	visitor.visit_type(&mut parameter.typing);
+	visitor.visit_identifier(&mut parameter.name);
+}
+
+pub fn walk_subroutine_body_mut<V: VisitorMut + ?Sized>(
+	visitor: &mut V,
+	body: &mut SubroutineBody,
+) {
+	for var_dec in &mut body.vars {
+		visitor.visit_var_dec(var_dec);
+	}
+	for statement in &mut body.statements {
+		visitor.visit_statement(statement);
+	}
+}
+
+pub fn walk_var_dec_mut<V: VisitorMut + ?Sized>(visitor: &mut V, var_dec: &mut VarDec) {
+	visitor.visit_type(&mut var_dec.typing);
+	for name in &mut var_dec.names {
+		visitor.visit_identifier(name);
+	}
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+	match statement {
+		Statement::Let(statement) => visitor.visit_let_statement(statement),
+		Statement::If(statement) => visitor.visit_if_statement(statement),
+		Statement::While(statement) => visitor.visit_while_statement(statement),
+		Statement::Do(statement) => visitor.visit_do_statement(statement),
+		Statement::Return(statement) => visitor.visit_return_statement(statement),
+	}
+}
+
+pub fn walk_let_statement_mut<V: VisitorMut + ?Sized>(
+	visitor: &mut V,
+	statement: &mut LetStatement,
+) {
+	visitor.visit_identifier(&mut statement.name);
+	if let Some(index) = &mut statement.index {
+		visitor.visit_expression(index);
+	}
+	visitor.visit_expression(&mut statement.value);
+}
+
+pub fn walk_if_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut IfStatement) {
+	visitor.visit_expression(&mut statement.condition);
+	for statement in &mut statement.statements {
+		visitor.visit_statement(statement);
+	}
+	if let Some(else_statements) = &mut statement.else_statements {
+		for statement in else_statements {
+			visitor.visit_statement(statement);
+		}
+	}
+}
+
+pub fn walk_while_statement_mut<V: VisitorMut + ?Sized>(
+	visitor: &mut V,
+	statement: &mut WhileStatement,
+) {
+	visitor.visit_expression(&mut statement.condition);
+	for statement in &mut statement.statements {
+		visitor.visit_statement(statement);
+	}
+}
+
+pub fn walk_do_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut DoStatement) {
+	visitor.visit_subroutine_call(&mut statement.call);
+}
+
+pub fn walk_return_statement_mut<V: VisitorMut + ?Sized>(
+	visitor: &mut V,
+	statement: &mut ReturnStatement,
+) {
+	if let Some(value) = &mut statement.value {
+		visitor.visit_expression(value);
+	}
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+	visitor.visit_term(&mut expression.term);
+	for (_, term) in &mut expression.operations {
+		visitor.visit_term(term);
+	}
+}
+
+pub fn walk_term_mut<V: VisitorMut + ?Sized>(visitor: &mut V, term: &mut Term) {
+	match term {
+		Term::IntegerConstant { .. }
+		| Term::StringConstant { .. }
+		| Term::KeywordConstant { .. } => {}
+		Term::Variable(name) => visitor.visit_identifier(name),
+		Term::ArrayAccess { name, index, .. } => {
+			visitor.visit_identifier(name);
+			visitor.visit_expression(index);
+		}
+		Term::SubroutineCall(call) => visitor.visit_subroutine_call(call),
+		Term::Parenthesized { expression, .. } => visitor.visit_expression(expression),
+		Term::Unary { term, .. } => visitor.visit_term(term),
+	}
+}
+
+pub fn walk_subroutine_call_mut<V: VisitorMut + ?Sized>(
+	visitor: &mut V,
+	call: &mut SubroutineCall,
+) {
+	if let Some(receiver) = &mut call.receiver {
+		visitor.visit_identifier(receiver);
+	}
+	visitor.visit_identifier(&mut call.name);
+	for argument in &mut call.arguments {
+		visitor.visit_expression(argument);
+	}
+}
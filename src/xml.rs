@@ -0,0 +1,248 @@
+use crate::tokenizer::{Token, TokenType};
+
+// Escapes the characters that are not allowed in XML text
+pub fn escape(text: &str) -> String {
+	let mut result = String::with_capacity(text.len());
+
+	for c in text.chars() {
+		match c {
+			'<' => result.push_str("&lt;"),
+			'>' => result.push_str("&gt;"),
+			'&' => result.push_str("&amp;"),
+			'"' => result.push_str("&quot;"),
+			_ => result.push(c),
+		}
+	}
+
+	result
+}
+
+// The element a token is written as, if it has one
+fn tag(token: &TokenType) -> Option<&'static str> {
+	match token {
+		TokenType::Keyword(_) => Some("keyword"),
+		TokenType::Symbol(_) => Some("symbol"),
+		TokenType::IntegerConstant => Some("integerConstant"),
+		TokenType::StringConstant => Some("stringConstant"),
+		TokenType::Identifier => Some("identifier"),
+		TokenType::Error(_) | TokenType::EndOfFile => None,
+	}
+}
+
+/// Returns the token as an XML element such as `<keyword> class </keyword>`,
+/// or `None` for tokens that do not appear in the analyzer output.
+pub fn token_element(token: &Token) -> Option<String> {
+	tag(&token.token).map(|tag| format!("<{}> {} </{}>", tag, escape(token.value), tag))
+}
+
+/// Serialises tokens in the format of the nand2tetris `xxxT.xml` files
+pub fn tokens_xml<'a, T>(tokens: T) -> String
+where
+	T: IntoIterator<Item = Token<'a>>,
+{
+	let mut result = String::from("<tokens>\n");
+
+	for token in tokens {
+		if let Some(element) = token_element(&token) {
+			result.push_str(&element);
+			result.push('\n');
+		}
+	}
+
+	result.push_str("</tokens>\n");
+	result
+}
+
+/// Builds the parse tree in the format of the nand2tetris `xxx.xml` files,
+/// with one element per line indented by two spaces per nesting level.
+#[derive(Default)]
+pub struct XmlWriter {
+	output: String,
+	depth: usize,
+}
+
+impl XmlWriter {
+	fn line(&mut self, line: &str) {
+		for _ in 0..self.depth {
+			self.output.push_str("  ");
+		}
+		self.output.push_str(line);
+		self.output.push('\n');
+	}
+
+	pub fn new() -> XmlWriter {
+		XmlWriter::default()
+	}
+
+	// Starts a non-terminal element such as `<class>`
+	pub fn open(&mut self, tag: &str) {
+		self.line(&format!("<{}>", tag));
+		self.depth += 1;
+	}
+
+	// Ends the innermost non-terminal element
+	pub fn close(&mut self, tag: &str) {
+		self.depth -= 1;
+		self.line(&format!("</{}>", tag));
+	}
+
+	// Writes a terminal element for the token
+	pub fn token(&mut self, token: &Token) {
+		if let Some(element) = token_element(token) {
+			self.line(&element);
+		}
+	}
+
+	pub fn finish(self) -> String {
+		self.output
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::parser::Parser;
+	use crate::tokenizer::Tokenizer;
+
+	const MAIN: &str = "class Main {
+	function void main() {
+		var int x;
+		let x = -x;
+		if ((x < 1) & (x > 2)) {
+			do Output.println();
+		}
+		return;
+	}
+}
+";
+
+	// The parse tree of `MAIN`, as written by the nand2tetris analyzer
+	const MAIN_XML: &str = "<class>
+  <keyword> class </keyword>
+  <identifier> Main </identifier>
+  <symbol> { </symbol>
+  <subroutineDec>
+    <keyword> function </keyword>
+    <keyword> void </keyword>
+    <identifier> main </identifier>
+    <symbol> ( </symbol>
+    <parameterList>
+    </parameterList>
+    <symbol> ) </symbol>
+    <subroutineBody>
+      <symbol> { </symbol>
+      <varDec>
+        <keyword> var </keyword>
+        <keyword> int </keyword>
+        <identifier> x </identifier>
+        <symbol> ; </symbol>
+      </varDec>
+      <statements>
+        <letStatement>
+          <keyword> let </keyword>
+          <identifier> x </identifier>
+          <symbol> = </symbol>
+          <expression>
+            <term>
+              <symbol> - </symbol>
+              <term>
+                <identifier> x </identifier>
+              </term>
+            </term>
+          </expression>
+          <symbol> ; </symbol>
+        </letStatement>
+        <ifStatement>
+          <keyword> if </keyword>
+          <symbol> ( </symbol>
+          <expression>
+            <term>
+              <symbol> ( </symbol>
+              <expression>
+                <term>
+                  <identifier> x </identifier>
+                </term>
+                <symbol> &lt; </symbol>
+                <term>
+                  <integerConstant> 1 </integerConstant>
+                </term>
+              </expression>
+              <symbol> ) </symbol>
+            </term>
+            <symbol> &amp; </symbol>
+            <term>
+              <symbol> ( </symbol>
+              <expression>
+                <term>
+                  <identifier> x </identifier>
+                </term>
+                <symbol> &gt; </symbol>
+                <term>
+                  <integerConstant> 2 </integerConstant>
+                </term>
+              </expression>
+              <symbol> ) </symbol>
+            </term>
+          </expression>
+          <symbol> ) </symbol>
+          <symbol> { </symbol>
+          <statements>
+            <doStatement>
+              <keyword> do </keyword>
+              <identifier> Output </identifier>
+              <symbol> . </symbol>
+              <identifier> println </identifier>
+              <symbol> ( </symbol>
+              <expressionList>
+              </expressionList>
+              <symbol> ) </symbol>
+              <symbol> ; </symbol>
+            </doStatement>
+          </statements>
+          <symbol> } </symbol>
+        </ifStatement>
+        <returnStatement>
+          <keyword> return </keyword>
+          <symbol> ; </symbol>
+        </returnStatement>
+      </statements>
+      <symbol> } </symbol>
+    </subroutineBody>
+  </subroutineDec>
+  <symbol> } </symbol>
+</class>
+";
+
+	#[test]
+	fn parse_tree() {
+		let xml = Parser::new(Tokenizer::new(MAIN, "Main.jack"))
+			.parse_xml()
+			.unwrap();
+
+		assert_eq!(xml, MAIN_XML);
+	}
+
+	#[test]
+	fn tokens() {
+		let tokens = Tokenizer::new("if (a < b & c > \"s\") {}", "Main.jack");
+
+		assert_eq!(
+			tokens_xml(tokens),
+			"<tokens>
+<keyword> if </keyword>
+<symbol> ( </symbol>
+<identifier> a </identifier>
+<symbol> &lt; </symbol>
+<identifier> b </identifier>
+<symbol> &amp; </symbol>
+<identifier> c </identifier>
+<symbol> &gt; </symbol>
+<stringConstant> s </stringConstant>
+<symbol> ) </symbol>
+<symbol> { </symbol>
+<symbol> } </symbol>
+</tokens>
+"
+		);
+	}
+}